@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+
+use argh::FromArgs;
+
+use crate::evaluate::{assignment_table, shortest_codes};
+use crate::{Character, Inputs, assign_jianma};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "collisions")]
+/// 分析全码重码：按全码分组，计算按字频加权的选重率，列出最严重的重码组，
+/// 并计算每个字都用最短编码输入时的实际选重率。
+pub struct CollisionsArgs {
+    #[argh(switch)]
+    /// 计算简码时允许空格简码。
+    space_jianma: bool,
+
+    #[argh(switch)]
+    /// 有简码的字不再出现在其全码的候选中（出简让全）。
+    hide_full: bool,
+
+    #[argh(option, default = "30")]
+    /// 最多列出的重码组数，0 表示全部列出。默认为 30。
+    top: usize,
+}
+
+/// 同一编码下的候选，按字频降序排列，第一位以外的字都需要选重。
+struct CodeGroup<'a> {
+    bianma: &'a str,
+    members: Vec<(char, u64)>,
+}
+
+impl CodeGroup<'_> {
+    /// 需要选重的字频，即第一位以外各字的字频之和。
+    fn selection_weight(&self) -> u64 {
+        self.members.iter().skip(1).map(|(_, weight)| weight).sum()
+    }
+}
+
+fn group_by_code<'a>(entries: impl IntoIterator<Item = (char, &'a str)>, weight_of: impl Fn(char) -> u64) -> Vec<CodeGroup<'a>> {
+    let mut by_code = HashMap::<&str, Vec<(char, u64)>>::new();
+    for (zi, bianma) in entries {
+        by_code.entry(bianma).or_default().push((zi, weight_of(zi)));
+    }
+
+    by_code
+        .into_iter()
+        .map(|(bianma, mut members)| {
+            members.sort_by(|a, b| a.1.cmp(&b.1).reverse().then(a.0.cmp(&b.0)));
+            members.dedup_by_key(|(zi, _)| *zi);
+            CodeGroup { bianma, members }
+        })
+        .collect()
+}
+
+fn weight_in(mabiao: &HashMap<char, Character>) -> impl Fn(char) -> u64 + '_ {
+    |zi| mabiao.get(&zi).map(|ch| ch.weight).unwrap_or(0)
+}
+
+pub fn run<W: Write>(writer: W, args: &CollisionsArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+    let weight_of = weight_in(&inputs.mabiao);
+    let total_weight = inputs.mabiao.values().map(|ch| ch.weight).sum::<u64>().max(1) as f64;
+
+    let mut groups = group_by_code(
+        inputs.full_mabiao.iter().map(|(zi, ch)| (*zi, ch.bianma.as_str())),
+        &weight_of,
+    );
+    groups.retain(|group| group.members.len() > 1);
+    groups.sort_by(|a, b| a.selection_weight().cmp(&b.selection_weight()).reverse().then(a.bianma.cmp(b.bianma)));
+
+    let colliding = groups.iter().map(|group| group.members.len()).sum::<usize>();
+    let selection = groups.iter().map(|group| group.selection_weight()).sum::<u64>();
+
+    writeln!(writer, "全码重码：{} 组，涉及 {colliding} 字", groups.len()).unwrap();
+    writeln!(writer, "全码选重率：{:.4}%", selection as f64 / total_weight * 100.0).unwrap();
+
+    let selection_keys = inputs.layout.selection_keys();
+    if !selection_keys.is_empty() {
+        // 首选用空格（或B区键）上屏，其余候选依次用选重键选取，再往后需要翻页。
+        let capacity = selection_keys.len() + 1;
+        let overflowing = groups.iter().filter(|group| group.members.len() > capacity).count();
+        let overflow_weight = groups
+            .iter()
+            .flat_map(|group| group.members.iter().skip(capacity))
+            .map(|(_, weight)| weight)
+            .sum::<u64>();
+        writeln!(
+            writer,
+            "选重键 {}：{overflowing} 组候选多于 {capacity} 个，需要翻页的字频占 {:.4}%",
+            selection_keys.iter().collect::<String>(),
+            overflow_weight as f64 / total_weight * 100.0,
+        ).unwrap();
+    }
+
+    let assignment = assign_jianma(inputs, args.space_jianma);
+    let table = assignment_table(inputs, &assignment, args.space_jianma);
+    let shortest = shortest_codes(&table, &inputs.commit);
+
+    // 每个编码下的所有候选：简码表中的条目，以及各字的全码（出简让全时略去有简码的字）。
+    let candidates = table
+        .iter()
+        .filter_map(|entry| {
+            let mut chars = entry.zi.chars();
+            match (chars.next(), chars.next()) {
+                (Some(zi), None) => Some((zi, entry.bianma.as_str())),
+                _ => None,
+            }
+        })
+        .chain(inputs.full_mabiao
+            .iter()
+            .filter(|(zi, _)| !(args.hide_full && shortest.contains_key(zi)))
+            .map(|(zi, ch)| (*zi, ch.bianma.as_str())))
+        .collect::<Vec<_>>();
+    let effective_groups = group_by_code(candidates, &weight_of);
+    let position = effective_groups
+        .iter()
+        .flat_map(|group| group.members
+            .iter()
+            .enumerate()
+            .map(move |(i, (zi, _))| ((*zi, group.bianma), i)))
+        .collect::<HashMap<_, _>>();
+
+    let mut effective_selection = 0;
+    let mut effective_groups_hit = Vec::new();
+    for (zi, character) in inputs.mabiao.iter() {
+        let bianma = shortest
+            .get(zi)
+            .filter(|bianma| bianma.len() <= character.bianma.len())
+            .map(|bianma| bianma.as_str())
+            .unwrap_or(character.bianma.as_str());
+
+        if position.get(&(*zi, bianma)).is_some_and(|i| *i > 0) {
+            effective_selection += character.weight;
+            effective_groups_hit.push((*zi, bianma, character.weight));
+        }
+    }
+    effective_groups_hit.sort_by(|a, b| a.2.cmp(&b.2).reverse().then(a.0.cmp(&b.0)));
+
+    writeln!(
+        writer,
+        "用最短编码输入时的选重率：{:.4}%（{}）",
+        effective_selection as f64 / total_weight * 100.0,
+        if args.hide_full { "出简让全" } else { "全码仍列在候选中" },
+    ).unwrap();
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "最严重的重码组（编码、选重字频、各字及其字频）：").unwrap();
+    let limit = if args.top == 0 { groups.len() } else { args.top };
+    for group in groups.iter().take(limit) {
+        let members = group.members
+            .iter()
+            .map(|(zi, weight)| match shortest.get(zi) {
+                Some(jianma) => format!("{zi}（{weight}，简码 {jianma}）"),
+                None => format!("{zi}（{weight}）"),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(writer, "{}\t{}\t{members}", group.bianma, group.selection_weight()).unwrap();
+    }
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "用最短编码输入时仍需选重的字：").unwrap();
+    let limit = if args.top == 0 { effective_groups_hit.len() } else { args.top };
+    for (zi, bianma, weight) in effective_groups_hit.iter().take(limit) {
+        writeln!(writer, "{zi}\t{bianma}\t{weight}").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_sorted_by_weight() {
+        let weights = HashMap::from([('只', 100), ('叭', 10), ('的', 500), ('叹', 10)]);
+        let entries = [('叭', "wpau"), ('只', "wpau"), ('叹', "wpau"), ('只', "wpau"), ('的', "ekoe"), ('只', "wu")];
+        let mut groups = group_by_code(entries, |zi| weights.get(&zi).copied().unwrap_or(0));
+        groups.sort_by_key(|group| group.bianma);
+
+        let summary = groups
+            .iter()
+            .map(|group| (group.bianma, group.members.clone(), group.selection_weight()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            ("ekoe", vec![('的', 500)], 0),
+            // 同一个字重复出现时只算一次，字频相同时按字排列。
+            ("wpau", vec![('只', 100), ('叭', 10), ('叹', 10)], 20),
+            ("wu", vec![('只', 100)], 0),
+        ]);
+    }
+}
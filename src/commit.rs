@@ -0,0 +1,474 @@
+//! 上屏规则：一个编码打完后是否还要按空格，是否立即上屏（顶屏）。
+//! 立即上屏的编码会挡住所有以它开头的更长编码，使之无法输入。
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use argh::FromArgs;
+use compact_str::CompactString;
+
+use crate::diff::read_jianma_table;
+use crate::evaluate::assignment_table;
+use crate::{Candidate, Inputs, Predefined, assign_jianma};
+
+/// 一条上屏规则。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// 所有编码都要按空格上屏。
+    Space,
+    /// 以B区键结尾的编码不必按空格，由下一字的首码顶屏；仍能继续打出以它开头的更长编码。
+    BArea,
+    /// 以B区键结尾的编码打完即上屏。
+    BAreaAuto,
+    /// 码长达到此值的编码打完即上屏，更长的编码无法输入。
+    MaxLength(usize),
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "space" => Ok(Self::Space),
+            "b-area" => Ok(Self::BArea),
+            "b-area-auto" => Ok(Self::BAreaAuto),
+            _ => match s.strip_prefix("max-length:").map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => Ok(Self::MaxLength(n)),
+                _ => Err(format!("未知的上屏规则：{s}")),
+            },
+        }
+    }
+}
+
+/// 上屏规则的组合，写法如 b-area,max-length:4。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitRules {
+    rules: Vec<Rule>,
+    b_area: Vec<char>,
+}
+
+impl FromStr for CommitRules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s
+            .split(',')
+            .map(|rule| rule.trim())
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| rule.parse())
+            .collect::<Result<Vec<Rule>, _>>()?;
+
+        if rules.is_empty() {
+            return Err(String::from("上屏规则为空"));
+        }
+        if rules.contains(&Rule::Space) && rules.len() > 1 {
+            return Err(String::from("space 不能与其他上屏规则同时使用"));
+        }
+
+        Ok(Self { rules, b_area: Vec::new() })
+    }
+}
+
+impl CommitRules {
+    /// 指定B区键。
+    pub fn with_b_area(self, b_area: &[char]) -> Self {
+        Self { b_area: b_area.to_vec(), ..self }
+    }
+
+    fn ends_in_b_area(&self, bianma: &str) -> bool {
+        bianma.ends_with(self.b_area.as_slice())
+    }
+
+    /// 打完编码后是否不必再按空格。
+    pub fn needs_no_space(&self, bianma: &str) -> bool {
+        self.rules.iter().any(|rule| match rule {
+            Rule::Space => false,
+            Rule::BArea | Rule::BAreaAuto => self.ends_in_b_area(bianma),
+            Rule::MaxLength(n) => bianma.len() >= *n,
+        })
+    }
+
+    /// 打完编码后是否立即上屏，不再等待后续的码。
+    pub fn commits_immediately(&self, bianma: &str) -> bool {
+        self.rules.iter().any(|rule| match rule {
+            Rule::BAreaAuto => self.ends_in_b_area(bianma),
+            Rule::MaxLength(n) => bianma.len() >= *n,
+            _ => false,
+        })
+    }
+
+    /// 是否有会挡住更长编码的规则。
+    pub fn has_immediate_commit(&self) -> bool {
+        self.rules.iter().any(|rule| matches!(rule, Rule::BAreaAuto | Rule::MaxLength(_)))
+    }
+
+    /// 是否为顶屏规则：以B区键结尾的编码由下一码顶屏上屏。
+    pub fn has_topping(&self) -> bool {
+        self.rules.contains(&Rule::BArea)
+    }
+
+    /// 能输入的最长码长。
+    pub fn max_length(&self) -> Option<usize> {
+        self.rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::MaxLength(n) => Some(*n),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// 输入一个编码所需的击键数：不必按空格的编码（默认为以B区键结尾的编码）只计码长，否则需要额外敲一次空格。
+    pub fn cost(&self, bianma: &str) -> usize {
+        if self.needs_no_space(bianma) {
+            bianma.len()
+        } else {
+            bianma.len() + 1
+        }
+    }
+
+    /// 挡住编码的立即上屏编码：编码的某个真前缀已被占用且打完即上屏时，编码无法输入。
+    pub fn blocking_prefix<'a>(&self, bianma: &'a str, occupied: &HashSet<CompactString>) -> Option<&'a str> {
+        (1..bianma.len())
+            .map(|i| &bianma[..i])
+            .find(|prefix| self.commits_immediately(prefix) && occupied.contains(*prefix))
+    }
+
+    /// 去掉在这些上屏规则下无法输入、或者会挡住已占用编码的候选。
+    pub fn retain_typeable<'a>(&self, candidates: &mut Vec<Candidate>, occupied: impl IntoIterator<Item = &'a CompactString>) {
+        if !self.has_immediate_commit() {
+            return;
+        }
+
+        let occupied = occupied.into_iter().cloned().collect::<HashSet<_>>();
+        let prefixes = occupied
+            .iter()
+            .flat_map(|bianma| (1..bianma.len()).map(|i| &bianma[..i]))
+            .collect::<HashSet<_>>();
+
+        candidates.retain(|cand| {
+            let too_long = self.max_length().is_some_and(|n| cand.bianma.len() > n);
+            let shadows = self.commits_immediately(&cand.bianma) && prefixes.contains(cand.bianma.as_str());
+            !too_long && !shadows && self.blocking_prefix(&cand.bianma, &occupied).is_none()
+        });
+    }
+
+    /// 同一阶段选出的简码中，被同阶段立即上屏的简码挡住、因而无法输入的编码。
+    /// retain_typeable 只能排除与阶段开始前已占用编码的冲突，同阶段的简码须在指派后再检查。
+    pub fn shadowed_within(&self, selected: &[Candidate]) -> HashSet<CompactString> {
+        if !self.has_immediate_commit() {
+            return HashSet::new();
+        }
+
+        let occupied = selected.iter().map(|cand| cand.bianma.clone()).collect::<HashSet<_>>();
+        selected
+            .iter()
+            .filter(|cand| self.blocking_prefix(&cand.bianma, &occupied).is_some())
+            .map(|cand| cand.bianma.clone())
+            .collect()
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "shadows")]
+/// 按 --commit 的上屏规则检查编码能否输入：列出立即上屏、挡住了更长编码的简码，因此无法输入的全码与字，
+/// 以及顶屏规则下后续的码可能被接着当作本码的B区简码。
+pub struct ShadowsArgs {
+    #[argh(switch)]
+    /// 计算简码时允许空格简码。
+    space_jianma: bool,
+
+    #[argh(option, default = "30")]
+    /// 每类最多列出的条数，0 表示全部列出。默认为 30。
+    top: usize,
+
+    #[argh(positional)]
+    /// 简码表路径（一行一个汉字与编码，用 \t 隔开）。不指定时检查按当前输入计算出的简码。
+    table: Option<PathBuf>,
+}
+
+pub fn run<W: Write>(writer: W, args: &ShadowsArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+    let commit = &inputs.commit;
+
+    let table = match &args.table {
+        Some(path) => read_jianma_table(path),
+        None => {
+            let assignment = assign_jianma(inputs, args.space_jianma);
+            assignment_table(inputs, &assignment, args.space_jianma)
+        }
+    };
+
+    // 所有能打的编码：简码表中的条目与各字的全码。
+    let entries = table
+        .iter()
+        .cloned()
+        .chain(inputs.full_mabiao
+            .iter()
+            .map(|(zi, ch)| Predefined { bianma: ch.bianma.clone(), zi: CompactString::from(zi.to_string()) }))
+        .collect::<Vec<_>>();
+    let occupied = entries.iter().map(|entry| entry.bianma.clone()).collect::<HashSet<_>>();
+    let mut by_code = HashMap::<&str, Vec<&str>>::new();
+    for entry in entries.iter() {
+        by_code.entry(entry.bianma.as_str()).or_default().push(entry.zi.as_str());
+    }
+
+    let weight_of = |zi: &str| {
+        let mut chars = zi.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => inputs.mabiao.get(&ch).map(|ch| ch.weight).unwrap_or(0),
+            _ => 0,
+        }
+    };
+
+    // 无法输入的编码，及挡住它的编码。
+    let mut blocked = Vec::new();
+    for entry in entries.iter() {
+        let too_long = commit.max_length().is_some_and(|n| entry.bianma.len() > n);
+        let prefix = commit.blocking_prefix(&entry.bianma, &occupied);
+        if too_long || prefix.is_some() {
+            blocked.push((entry, prefix));
+        }
+    }
+    let blocked_codes = blocked.iter().map(|(entry, _)| entry.bianma.as_str()).collect::<HashSet<_>>();
+
+    // 所有编码都无法输入的字。
+    let mut typeable = HashSet::new();
+    for entry in entries.iter().filter(|entry| !blocked_codes.contains(entry.bianma.as_str())) {
+        typeable.insert(entry.zi.as_str());
+    }
+    let mut untypeable = inputs.full_mabiao
+        .keys()
+        .map(|zi| zi.to_string())
+        .filter(|zi| !typeable.contains(zi.as_str()))
+        .collect::<Vec<_>>();
+    untypeable.sort_by(|a, b| weight_of(b).cmp(&weight_of(a)).then(a.cmp(b)));
+
+    let total_weight = inputs.mabiao.values().map(|ch| ch.weight).sum::<u64>().max(1) as f64;
+    let untypeable_weight = untypeable.iter().map(|zi| weight_of(zi)).sum::<u64>();
+
+    writeln!(writer, "无法输入的编码：{} 个", blocked.len()).unwrap();
+    writeln!(
+        writer,
+        "无法输入的字：{} 个，字频占 {:.4}%",
+        untypeable.len(),
+        untypeable_weight as f64 / total_weight * 100.0,
+    ).unwrap();
+
+    // 挡住其他编码的立即上屏编码。
+    let mut shadowing = BTreeMap::<&str, Vec<&Predefined>>::new();
+    for (entry, prefix) in blocked.iter() {
+        if let Some(prefix) = prefix {
+            shadowing.entry(prefix).or_default().push(entry);
+        }
+    }
+    let mut shadowing = shadowing.into_iter().collect::<Vec<_>>();
+    shadowing.sort_by_key(|(_, entries)| std::cmp::Reverse(entries.iter().map(|entry| weight_of(&entry.zi)).sum::<u64>()));
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "挡住了更长编码的简码（编码、字、被挡住的编码与字）：").unwrap();
+    let limit = if args.top == 0 { shadowing.len() } else { args.top };
+    for (prefix, entries) in shadowing.iter().take(limit) {
+        let owners = by_code.get(prefix).map(|zis| zis.join("、")).unwrap_or_default();
+        let mut entries = entries.clone();
+        entries.sort_by(|a, b| weight_of(&b.zi).cmp(&weight_of(&a.zi)).then(a.bianma.cmp(&b.bianma)));
+        let shadowed = entries
+            .iter()
+            .take(10)
+            .map(|entry| format!("{} {}", entry.bianma, entry.zi))
+            .collect::<Vec<_>>()
+            .join("，");
+        if entries.len() > 10 {
+            writeln!(writer, "{prefix}\t{owners}\t{shadowed} 等 {} 个", entries.len()).unwrap();
+        } else {
+            writeln!(writer, "{prefix}\t{owners}\t{shadowed}").unwrap();
+        }
+    }
+
+    if let Some(n) = commit.max_length() {
+        let too_long = blocked.iter().filter(|(entry, _)| entry.bianma.len() > n).count();
+        writeln!(writer).unwrap();
+        writeln!(writer, "长于 {n} 码、无法输入的编码：{too_long} 个").unwrap();
+    }
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "无法输入的字（字与字频）：").unwrap();
+    let limit = if args.top == 0 { untypeable.len() } else { args.top };
+    for zi in untypeable.iter().take(limit) {
+        writeln!(writer, "{zi}\t{}", weight_of(zi)).unwrap();
+    }
+
+    if commit.has_topping() {
+        // 顶屏：以B区键结尾的编码后紧接的码若能接成更长的编码，会被当作本码的延续，须先按空格。
+        let prefixes = occupied
+            .iter()
+            .flat_map(|bianma| (1..bianma.len()).map(|i| &bianma[..i]))
+            .collect::<HashSet<_>>();
+        let mut ambiguous = table
+            .iter()
+            .filter(|entry| commit.needs_no_space(&entry.bianma) && prefixes.contains(entry.bianma.as_str()))
+            .collect::<Vec<_>>();
+        ambiguous.sort_by(|a, b| weight_of(&b.zi).cmp(&weight_of(&a.zi)).then(a.bianma.cmp(&b.bianma)));
+
+        writeln!(writer).unwrap();
+        writeln!(
+            writer,
+            "顶屏时可能被后续的码接续的简码：{} 个（编码为其他编码的前缀，后一字的首码能接上时须先按空格）",
+            ambiguous.len(),
+        ).unwrap();
+        let limit = if args.top == 0 { ambiguous.len() } else { args.top };
+        for entry in ambiguous.iter().take(limit) {
+            writeln!(writer, "{}\t{}\t{}", entry.zi, entry.bianma, weight_of(&entry.zi)).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Factors, Stage};
+
+    fn rules(s: &str) -> CommitRules {
+        s.parse::<CommitRules>().unwrap().with_b_area(&['a', 'e', 'i', 'o', 'u'])
+    }
+
+    fn candidate(bianma: &str) -> Candidate {
+        scored_candidate("字", bianma, 1)
+    }
+
+    fn scored_candidate(zi: &str, bianma: &str, score: u64) -> Candidate {
+        Candidate {
+            zi: CompactString::from(zi),
+            bianma: CompactString::from(bianma),
+            frequency: 1,
+            stage: Stage::Suffix,
+            factors: Factors { length: 1.0, zigen: 1.0, space: 1.0, family: 1.0, typo: 1.0 },
+            score,
+        }
+    }
+
+    fn codes(candidates: &[Candidate]) -> Vec<(&str, &str)> {
+        let mut codes = candidates
+            .iter()
+            .map(|cand| (cand.zi.as_str(), cand.bianma.as_str()))
+            .collect::<Vec<_>>();
+        codes.sort();
+        codes
+    }
+
+    fn occupied(codes: &[&str]) -> HashSet<CompactString> {
+        codes.iter().map(|code| CompactString::from(*code)).collect()
+    }
+
+    #[test]
+    fn parse_rules() {
+        assert_eq!("b-area".parse::<CommitRules>().unwrap().rules, vec![Rule::BArea]);
+        assert_eq!(
+            " b-area , max-length:4 ".parse::<CommitRules>().unwrap().rules,
+            vec![Rule::BArea, Rule::MaxLength(4)],
+        );
+        for invalid in ["", ",", "max-length:0", "max-length:x", "max-length", "space,b-area", "enter"] {
+            assert!(invalid.parse::<CommitRules>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn space_needs_space_for_every_code() {
+        let space = rules("space");
+        assert_eq!(space.cost("a"), 2);
+        assert_eq!(space.cost("abcd"), 5);
+        assert!(!space.has_immediate_commit());
+        assert!(!space.has_topping());
+        assert_eq!(space.max_length(), None);
+    }
+
+    #[test]
+    fn b_area_tops_without_shadowing() {
+        let b_area = rules("b-area");
+        assert_eq!(b_area.cost("ba"), 2);
+        assert_eq!(b_area.cost("bc"), 3);
+        assert!(b_area.has_topping());
+        assert!(!b_area.commits_immediately("ba"));
+        assert_eq!(b_area.blocking_prefix("bao", &occupied(&["ba"])), None);
+
+        // 默认规则下不去掉任何候选。
+        let mut candidates = vec![candidate("ba"), candidate("bao")];
+        b_area.retain_typeable(&mut candidates, &occupied(&["bao", "ba"]));
+        assert_eq!(candidates.len(), 2);
+    }
+
+    #[test]
+    fn max_length_commits_and_limits() {
+        let max = rules("max-length:3");
+        assert_eq!(max.max_length(), Some(3));
+        assert_eq!(max.cost("bcd"), 3);
+        assert_eq!(max.cost("bc"), 3);
+        assert!(max.commits_immediately("bcd"));
+        assert!(!max.commits_immediately("bc"));
+        assert_eq!(max.blocking_prefix("bcdf", &occupied(&["bcd"])), Some("bcd"));
+        assert_eq!(max.blocking_prefix("bcdf", &occupied(&["bc"])), None);
+
+        let mut candidates = vec![candidate("bc"), candidate("bcdf")];
+        max.retain_typeable(&mut candidates, &occupied(&[]));
+        assert_eq!(candidates.iter().map(|cand| cand.bianma.as_str()).collect::<Vec<_>>(), vec!["bc"]);
+    }
+
+    #[test]
+    fn combined_rules_use_the_shortest_max_length() {
+        let combined = rules("b-area,max-length:4,max-length:3");
+        assert_eq!(combined.max_length(), Some(3));
+        assert!(combined.has_topping());
+        assert!(combined.has_immediate_commit());
+    }
+
+    #[test]
+    fn b_area_auto_shadows_longer_codes() {
+        let auto = rules("b-area-auto");
+        assert!(auto.commits_immediately("ba"));
+        assert!(!auto.commits_immediately("bc"));
+        assert_eq!(auto.blocking_prefix("bao", &occupied(&["ba"])), Some("ba"));
+        assert_eq!(auto.blocking_prefix("bco", &occupied(&["bc"])), None);
+        assert_eq!(auto.blocking_prefix("ba", &occupied(&["ba"])), None);
+
+        // ba 会挡住已占用的全码 bao；bio 会被已占用的 bi 挡住；bco 与 bc 都能输入。
+        let mut candidates = vec![candidate("ba"), candidate("bio"), candidate("bco"), candidate("bc")];
+        auto.retain_typeable(&mut candidates, &occupied(&["bao", "bi"]));
+        assert_eq!(
+            candidates.iter().map(|cand| cand.bianma.as_str()).collect::<Vec<_>>(),
+            vec!["bco", "bc"],
+        );
+    }
+
+    #[test]
+    fn shadowing_within_a_stage() {
+        let auto = rules("b-area-auto");
+        let selected = vec![scored_candidate("甲", "ba", 10), scored_candidate("乙", "bao", 5), scored_candidate("丙", "bc", 3)];
+        assert_eq!(auto.shadowed_within(&selected), occupied(&["bao"]));
+        assert!(rules("b-area").shadowed_within(&selected).is_empty());
+    }
+
+    #[test]
+    fn stage_is_solved_again_without_shadowed_codes() {
+        // 甲取 ba 后立即上屏，同阶段的乙不能再取以 ba 开头的 bao，只能退而取 bco。
+        let mut candidates = vec![
+            scored_candidate("甲", "ba", 10),
+            scored_candidate("乙", "bao", 8),
+            scored_candidate("乙", "bco", 2),
+        ];
+        let (score, selected) = crate::make_typeable_jianma_table(&mut candidates, &rules("b-area-auto"));
+        assert_eq!(codes(&selected), vec![("乙", "bco"), ("甲", "ba")]);
+        assert_eq!(score, 12);
+
+        // 不会立即上屏时两者可以共存。
+        let mut candidates = vec![
+            scored_candidate("甲", "ba", 10),
+            scored_candidate("乙", "bao", 8),
+            scored_candidate("乙", "bco", 2),
+        ];
+        let (score, selected) = crate::make_typeable_jianma_table(&mut candidates, &rules("b-area"));
+        assert_eq!(codes(&selected), vec![("乙", "bao"), ("甲", "ba")]);
+        assert_eq!(score, 18);
+    }
+}
@@ -0,0 +1,44 @@
+//! 拆分表，一行一个字与它的字根序列（字根之间用空格隔开），如 `㐂\t七 七 七`。
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use compact_str::CompactString;
+
+use crate::{Character, read_file};
+
+/// 各字的字根序列。
+pub type Decomposition = HashMap<char, Vec<CompactString>>;
+
+pub fn read_decomposition(path: &Path) -> Decomposition {
+    let mut result = HashMap::new();
+
+    read_file(path, "拆分表", |line_no, line| {
+        let Some((zi, roots)) = line.split_once('\t') else {
+            panic!("拆分表第{}行存在错误", line_no + 1);
+        };
+
+        let mut chars = zi.chars();
+        let (Some(zi), None) = (chars.next(), chars.next()) else {
+            panic!("拆分表第{}行的字无效", line_no + 1);
+        };
+
+        let roots = roots.split_whitespace().map(CompactString::new).collect::<Vec<_>>();
+        if roots.is_empty() {
+            panic!("拆分表第{}行没有字根", line_no + 1);
+        }
+
+        result.insert(zi, roots);
+    });
+
+    result
+}
+
+/// 以拆分表中的字根数作为字根数量。
+pub fn apply_zigen_count(mabiao: &mut HashMap<char, Character>, decomposition: &Decomposition) {
+    for (zi, character) in mabiao.iter_mut() {
+        if let Some(roots) = decomposition.get(zi) {
+            character.zigen_count = roots.len() as u64;
+        }
+    }
+}
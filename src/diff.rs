@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use argh::FromArgs;
+use compact_str::CompactString;
+use serde::Serialize;
+
+use crate::commit::CommitRules;
+use crate::evaluate::{Metrics, evaluate_table, write_metrics_header, write_metrics_row};
+use crate::{Character, Predefined, read_file};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "diff")]
+/// 比较多张简码表。第一张表为基准，其余各表分别与之比较。
+pub struct DiffArgs {
+    #[argh(switch)]
+    /// 以JSON格式输出比较结果。
+    json: bool,
+
+    #[argh(option, default = "20")]
+    /// 每一类差异最多列出的条目数，0 表示全部列出。默认为 20。
+    limit: usize,
+
+    #[argh(positional)]
+    /// 简码表路径（一行一个汉字与编码，用 \t 隔开）。
+    tables: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TableReport {
+    path: PathBuf,
+    metrics: Metrics,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct EntryChange {
+    zi: CompactString,
+    from: Vec<CompactString>,
+    to: Vec<CompactString>,
+    weight: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CodeChange {
+    bianma: CompactString,
+    from: Vec<CompactString>,
+    to: Vec<CompactString>,
+    weight: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Comparison {
+    base: PathBuf,
+    other: PathBuf,
+    added: Vec<EntryChange>,
+    removed: Vec<EntryChange>,
+    moved: Vec<EntryChange>,
+    reassigned: Vec<CodeChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DiffReport {
+    tables: Vec<TableReport>,
+    comparisons: Vec<Comparison>,
+}
+
+pub fn read_jianma_table(path: &Path) -> Vec<Predefined> {
+    let mut result = Vec::new();
+
+    read_file(path, "简码表", |line_no, line| {
+        if let Some((prefix, suffix)) = line.split_once('\t') {
+            let bianma = CompactString::from_str_to_lowercase(suffix.trim_ascii());
+            let zi = CompactString::new(prefix);
+            result.push(Predefined { bianma, zi });
+        } else {
+            panic!("简码表 {} 第{}行存在错误", path.display(), line_no + 1);
+        }
+    });
+
+    result
+}
+
+fn zi_weight(mabiao: &HashMap<char, Character>, zi: &str) -> u64 {
+    let mut chars = zi.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => mabiao.get(&ch).map(|c| c.weight).unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn group<'a>(
+    table: &'a [Predefined],
+    key: impl Fn(&'a Predefined) -> &'a CompactString,
+    value: impl Fn(&'a Predefined) -> &'a CompactString,
+) -> BTreeMap<&'a CompactString, BTreeSet<&'a CompactString>> {
+    let mut result = BTreeMap::<_, BTreeSet<_>>::new();
+    for entry in table.iter() {
+        result.entry(key(entry)).or_default().insert(value(entry));
+    }
+    result
+}
+
+fn to_vec(set: Option<&BTreeSet<&CompactString>>) -> Vec<CompactString> {
+    set.map(|set| set.iter().map(|s| (*s).clone()).collect())
+        .unwrap_or_default()
+}
+
+fn compare(
+    mabiao: &HashMap<char, Character>,
+    (base_path, base): (&Path, &[Predefined]),
+    (other_path, other): (&Path, &[Predefined]),
+) -> Comparison {
+    let base_by_zi = group(base, |e| &e.zi, |e| &e.bianma);
+    let other_by_zi = group(other, |e| &e.zi, |e| &e.bianma);
+    let base_by_code = group(base, |e| &e.bianma, |e| &e.zi);
+    let other_by_code = group(other, |e| &e.bianma, |e| &e.zi);
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut moved = Vec::new();
+    let mut reassigned = Vec::new();
+
+    let all_zi = base_by_zi.keys().chain(other_by_zi.keys()).collect::<BTreeSet<_>>();
+    for zi in all_zi {
+        let from = base_by_zi.get(zi);
+        let to = other_by_zi.get(zi);
+        if from == to {
+            continue
+        }
+
+        let change = EntryChange {
+            zi: (*zi).clone(),
+            from: to_vec(from),
+            to: to_vec(to),
+            weight: zi_weight(mabiao, zi),
+        };
+
+        match (from, to) {
+            (None, Some(_)) => added.push(change),
+            (Some(_), None) => removed.push(change),
+            _ => moved.push(change),
+        }
+    }
+
+    for (bianma, from) in base_by_code.iter() {
+        let Some(to) = other_by_code.get(bianma) else {
+            continue
+        };
+
+        if from != to {
+            let weight = from.iter()
+                .chain(to.iter())
+                .map(|zi| zi_weight(mabiao, zi))
+                .max()
+                .unwrap_or(0);
+
+            reassigned.push(CodeChange {
+                bianma: (*bianma).clone(),
+                from: to_vec(Some(from)),
+                to: to_vec(Some(to)),
+                weight,
+            });
+        }
+    }
+
+    added.sort_by(|a, b| a.weight.cmp(&b.weight).reverse());
+    removed.sort_by(|a, b| a.weight.cmp(&b.weight).reverse());
+    moved.sort_by(|a, b| a.weight.cmp(&b.weight).reverse());
+    reassigned.sort_by(|a, b| a.weight.cmp(&b.weight).reverse());
+
+    Comparison {
+        base: base_path.to_path_buf(),
+        other: other_path.to_path_buf(),
+        added,
+        removed,
+        moved,
+        reassigned,
+    }
+}
+
+fn write_changes<W: Write>(
+    writer: &mut W,
+    title: &str,
+    changes: &[EntryChange],
+    limit: usize,
+) {
+    let total = changes.iter().map(|c| c.weight).sum::<u64>();
+    writeln!(writer, "{title}：{} 条，字频合计 {total}", changes.len()).unwrap();
+
+    let limit = if limit == 0 { changes.len() } else { limit };
+    for change in changes.iter().take(limit) {
+        writeln!(
+            writer,
+            "  {}\t{} -> {}\t{}",
+            change.zi,
+            change.from.join(" "),
+            change.to.join(" "),
+            change.weight,
+        ).unwrap();
+    }
+}
+
+fn write_report<W: Write>(writer: W, report: &DiffReport, limit: usize) {
+    let mut writer = BufWriter::new(writer);
+
+    writeln!(writer, "简码表评估：").unwrap();
+    write_metrics_header(&mut writer);
+    for table in report.tables.iter() {
+        write_metrics_row(&mut writer, &table.metrics, &table.path.display().to_string());
+    }
+
+    for comparison in report.comparisons.iter() {
+        writeln!(writer).unwrap();
+        writeln!(
+            writer,
+            "{} => {}",
+            comparison.base.display(),
+            comparison.other.display(),
+        ).unwrap();
+
+        write_changes(&mut writer, "新增", &comparison.added, limit);
+        write_changes(&mut writer, "删除", &comparison.removed, limit);
+        write_changes(&mut writer, "变更", &comparison.moved, limit);
+
+        writeln!(writer, "易主编码：{} 条", comparison.reassigned.len()).unwrap();
+        let limit = if limit == 0 { comparison.reassigned.len() } else { limit };
+        for change in comparison.reassigned.iter().take(limit) {
+            writeln!(
+                writer,
+                "  {}\t{} -> {}\t{}",
+                change.bianma,
+                change.from.join(" "),
+                change.to.join(" "),
+                change.weight,
+            ).unwrap();
+        }
+    }
+}
+
+pub fn run<W: Write>(
+    writer: W,
+    args: &DiffArgs,
+    mabiao: &HashMap<char, Character>,
+    commit: &CommitRules,
+) {
+    if args.tables.len() < 2 {
+        panic!("至少需要两张简码表才能比较");
+    }
+
+    let tables = args.tables
+        .iter()
+        .map(|path| (path.as_path(), read_jianma_table(path)))
+        .collect::<Vec<_>>();
+
+    let report = DiffReport {
+        tables: tables
+            .iter()
+            .map(|(path, table)| TableReport {
+                path: path.to_path_buf(),
+                metrics: evaluate_table(mabiao, table, commit),
+            })
+            .collect(),
+        comparisons: tables[1..]
+            .iter()
+            .map(|(path, table)| {
+                compare(mabiao, (tables[0].0, &tables[0].1), (path, table))
+            })
+            .collect(),
+    };
+
+    if args.json {
+        let mut writer = BufWriter::new(writer);
+        serde_json::to_writer_pretty(&mut writer, &report).expect("无法输出比较结果");
+        writeln!(writer).unwrap();
+    } else {
+        write_report(writer, &report, args.limit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn read(name: &str, content: &str) -> Vec<Predefined> {
+        let path = std::env::temp_dir().join(format!("makejian-{}-jianma-{name}.txt", std::process::id()));
+        fs::write(&path, content).unwrap();
+        let table = read_jianma_table(&path);
+        fs::remove_file(&path).unwrap();
+        table
+    }
+
+    fn entry(zi: &str, bianma: &str) -> Predefined {
+        Predefined { bianma: CompactString::from(bianma), zi: CompactString::from(zi) }
+    }
+
+    fn character(weight: u64) -> Character {
+        Character { bianma: CompactString::from("aaaa"), weight, zigen_count: 1, frequencies: vec![weight] }
+    }
+
+    #[test]
+    fn read_table() {
+        let table = read("ok", "# 简码表\n的\tE\n\n一下\tyx \n");
+        assert_eq!(table, vec![entry("的", "e"), entry("一下", "yx")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "第2行存在错误")]
+    fn read_table_without_tab() {
+        read("bad", "的\te\n了 le\n");
+    }
+
+    #[test]
+    fn compare_tables() {
+        let mabiao = HashMap::from([('的', character(100)), ('了', character(50)), ('是', character(30)), ('在', character(10))]);
+        let base = vec![entry("的", "e"), entry("了", "le"), entry("是", "u"), entry("一下", "yx")];
+        let other = vec![entry("的", "e"), entry("了", "u"), entry("是", "le"), entry("在", "za")];
+        let comparison = compare(&mabiao, (Path::new("a.txt"), &base), (Path::new("b.txt"), &other));
+
+        let changes = |changes: &[EntryChange]| {
+            changes
+                .iter()
+                .map(|change| (change.zi.to_string(), change.from.join(" "), change.to.join(" "), change.weight))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(changes(&comparison.added), vec![(String::from("在"), String::new(), String::from("za"), 10)]);
+        assert_eq!(changes(&comparison.removed), vec![(String::from("一下"), String::from("yx"), String::new(), 0)]);
+        assert_eq!(
+            changes(&comparison.moved),
+            vec![
+                (String::from("了"), String::from("le"), String::from("u"), 50),
+                (String::from("是"), String::from("u"), String::from("le"), 30),
+            ],
+        );
+
+        let reassigned = comparison.reassigned
+            .iter()
+            .map(|change| (change.bianma.as_str(), change.weight))
+            .collect::<Vec<_>>();
+        assert_eq!(reassigned, vec![("le", 50), ("u", 50)]);
+    }
+}
@@ -0,0 +1,179 @@
+//! 由拆分表、字根键位表与取码规则推出各字的全码，用于试验不同的字根布局。
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use compact_str::CompactString;
+
+use crate::decomposition::Decomposition;
+use crate::rule::CodingRule;
+use crate::{Character, read_file};
+
+/// 默认的单字取码规则：一根取大码与小码，两根各取大码与小码，三根取三个大码与末根小码，
+/// 四根及以上取前三根大码与末根小码。
+pub const DEFAULT_ROOT_RULE: &str = "1:Aa,2:AaBb,3:ABCc,4+:ABCz";
+
+/// 读取字根键位表，一行一个字根与它的编码（用 \t 隔开），编码的第一码为大码，第二码为小码。
+pub fn read_root_keys(path: &Path) -> HashMap<CompactString, CompactString> {
+    let mut result = HashMap::new();
+
+    read_file(path, "字根键位表", |line_no, line| {
+        let Some((root, keys)) = line.split_once('\t') else {
+            panic!("字根键位表第{}行存在错误", line_no + 1);
+        };
+
+        let keys = CompactString::from_str_to_lowercase(keys.trim());
+        if keys.is_empty() {
+            panic!("字根键位表第{}行缺少编码", line_no + 1);
+        }
+
+        result.insert(CompactString::new(root.trim()), keys);
+    });
+
+    result
+}
+
+/// 按拆分表为码表中的字重新编码。码表为空时，为拆分表中的所有字编码。
+/// 有字根不在键位表中、或者按规则取不出码的字会被移出码表，并在标准错误中提示。
+pub fn encode_mabiao(
+    mabiao: &mut HashMap<char, Character>,
+    decomposition: &Decomposition,
+    root_keys: &HashMap<CompactString, CompactString>,
+    rule: &CodingRule,
+) {
+    if mabiao.is_empty() {
+        for zi in decomposition.keys() {
+            mabiao.insert(*zi, Character {
+                bianma: CompactString::new(""),
+                weight: 0,
+                zigen_count: 0,
+                frequencies: Vec::new(),
+            });
+        }
+    }
+
+    let mut missing_roots = HashMap::<&str, usize>::new();
+    let mut failed = Vec::new();
+
+    mabiao.retain(|zi, character| {
+        let Some(roots) = decomposition.get(zi) else {
+            failed.push(*zi);
+            return false;
+        };
+
+        let keys = roots
+            .iter()
+            .map(|root| match root_keys.get(root) {
+                Some(keys) => Some(keys.as_str()),
+                None => {
+                    *missing_roots.entry(root.as_str()).or_default() += 1;
+                    None
+                }
+            })
+            .collect::<Option<Vec<_>>>();
+
+        match keys.and_then(|keys| rule.apply(&keys)) {
+            Some(bianma) => {
+                character.bianma = bianma;
+                true
+            }
+            None => {
+                failed.push(*zi);
+                false
+            }
+        }
+    });
+
+    if !failed.is_empty() {
+        failed.sort();
+        let examples = failed.iter().take(10).collect::<String>();
+        eprintln!("警告：{} 个字无法编码，已移出码表，如 {examples}", failed.len());
+    }
+
+    if !missing_roots.is_empty() {
+        let mut missing_roots = missing_roots.into_iter().collect::<Vec<_>>();
+        missing_roots.sort_by(|a, b| a.1.cmp(&b.1).reverse().then(a.0.cmp(b.0)));
+        let examples = missing_roots
+            .iter()
+            .take(10)
+            .map(|(root, count)| format!("{root}（{count} 字）"))
+            .collect::<Vec<_>>()
+            .join("、");
+        eprintln!("警告：{} 个字根不在字根键位表中，如 {examples}", missing_roots.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn read(name: &str, content: &str) -> HashMap<CompactString, CompactString> {
+        let path = std::env::temp_dir().join(format!("makejian-{}-roots-{name}.txt", std::process::id()));
+        fs::write(&path, content).unwrap();
+        let root_keys = read_root_keys(&path);
+        fs::remove_file(&path).unwrap();
+        root_keys
+    }
+
+    fn decomposition() -> Decomposition {
+        [('口', "口"), ('吕', "口 口"), ('品', "口 口 口"), ('器', "口 口 犬 口 口"), ('哭', "口 口 犬"), ('㗊', "口 口 口 口 米")]
+            .into_iter()
+            .map(|(zi, roots)| (zi, roots.split(' ').map(CompactString::from).collect()))
+            .collect()
+    }
+
+    fn bianma(mabiao: &HashMap<char, Character>) -> Vec<(char, &str)> {
+        let mut result = mabiao.iter().map(|(zi, ch)| (*zi, ch.bianma.as_str())).collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn read_root_key_file() {
+        let root_keys = read("ok", "# 字根\t编码\n口\tKO\n 犬 \tqu \n\n");
+        assert_eq!(root_keys.len(), 2);
+        assert_eq!(root_keys["口"], "ko");
+        assert_eq!(root_keys["犬"], "qu");
+    }
+
+    #[test]
+    #[should_panic(expected = "字根键位表第2行存在错误")]
+    fn read_root_key_file_without_tab() {
+        read("tab", "口\tko\n犬 qu\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "字根键位表第1行缺少编码")]
+    fn read_root_key_file_without_keys() {
+        read("keys", "口\t\u{3000}\n");
+    }
+
+    #[test]
+    fn encode_all_decomposed_characters() {
+        let root_keys = read("encode", "口\tko\n犬\tqu\n");
+        let rule = DEFAULT_ROOT_RULE.parse::<CodingRule>().unwrap();
+        let mut mabiao = HashMap::new();
+        encode_mabiao(&mut mabiao, &decomposition(), &root_keys, &rule);
+
+        // 㗊 的末根「米」不在键位表中，无法编码。
+        assert_eq!(
+            bianma(&mabiao),
+            vec![('口', "ko"), ('吕', "koko"), ('品', "kkko"), ('哭', "kkqu"), ('器', "kkqo")],
+        );
+    }
+
+    #[test]
+    fn encode_only_characters_in_mabiao() {
+        let root_keys = read("mabiao", "口\tko\n犬\tqu\n");
+        let rule = DEFAULT_ROOT_RULE.parse::<CodingRule>().unwrap();
+        let character = Character { bianma: CompactString::from("zzzz"), weight: 10, zigen_count: 0, frequencies: vec![10] };
+        let mut mabiao = HashMap::from([('吕', character.clone()), ('回', character)]);
+        encode_mabiao(&mut mabiao, &decomposition(), &root_keys, &rule);
+
+        // 回 不在拆分表中，被移出码表。
+        assert_eq!(bianma(&mabiao), vec![('吕', "koko")]);
+        assert_eq!(mabiao[&'吕'].weight, 10);
+    }
+}
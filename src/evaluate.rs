@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use argh::FromArgs;
+use compact_str::CompactString;
+use serde::Serialize;
+
+use crate::commit::CommitRules;
+use crate::diff::read_jianma_table;
+use crate::{Assignment, Character, Inputs, Predefined, assign_jianma, collect_selected_jianma, with_scoring_args};
+
+with_scoring_args! {
+#[derive(FromArgs)]
+#[argh(subcommand, name = "evaluate")]
+/// 评估简码表。不指定简码表时，评估按当前输入计算出的简码，部件家族奖励与误触惩罚只用于此时的计算。
+pub struct EvaluateArgs {
+    #[argh(switch)]
+    /// 评估计算出的简码时，允许空格简码。
+    space_jianma: bool,
+
+    #[argh(switch)]
+    /// 以JSON格式输出评估结果。
+    json: bool,
+
+    #[argh(positional)]
+    /// 简码表路径（一行一个汉字与编码，用 \t 隔开）。
+    tables: Vec<PathBuf>,
+}
+}
+
+/// 简码表的评估指标。所有加权数值均以字频为权重。
+#[derive(Debug, Clone, Serialize)]
+pub struct Metrics {
+    /// 简码表的条目数。
+    pub entries: usize,
+    /// 拥有简码的单字数。
+    pub characters: usize,
+    /// 字频加权的简码覆盖率。
+    pub coverage: f64,
+    /// 使用简码表后，每字的加权平均击键数。
+    pub keystrokes: f64,
+    /// 只使用全码时，每字的加权平均击键数。
+    pub full_keystrokes: f64,
+}
+
+/// 找出简码表中每个单字击键数最少的编码。
+pub fn shortest_codes<'a>(table: &'a [Predefined], commit: &CommitRules) -> HashMap<char, &'a CompactString> {
+    let mut shortest = HashMap::<char, &CompactString>::new();
+
+    for entry in table.iter() {
+        let mut chars = entry.zi.chars();
+        let (Some(zi), None) = (chars.next(), chars.next()) else {
+            continue
+        };
+
+        let current = shortest.entry(zi).or_insert(&entry.bianma);
+        if commit.cost(&entry.bianma) < commit.cost(current) {
+            *current = &entry.bianma;
+        }
+    }
+
+    shortest
+}
+
+pub fn evaluate_table(
+    mabiao: &HashMap<char, Character>,
+    table: &[Predefined],
+    commit: &CommitRules,
+) -> Metrics {
+    let shortest = shortest_codes(table, commit);
+
+    let mut total_weight = 0.0;
+    let mut covered_weight = 0.0;
+    let mut keystrokes = 0.0;
+    let mut full_keystrokes = 0.0;
+
+    for (zi, character) in mabiao.iter() {
+        let weight = character.weight as f64;
+        let full_cost = commit.cost(&character.bianma);
+        let cost = shortest
+            .get(zi)
+            .map(|bianma| commit.cost(bianma).min(full_cost))
+            .unwrap_or(full_cost);
+
+        total_weight += weight;
+        keystrokes += weight * cost as f64;
+        full_keystrokes += weight * full_cost as f64;
+
+        if shortest.contains_key(zi) {
+            covered_weight += weight;
+        }
+    }
+
+    let total_weight = total_weight.max(1.0);
+
+    Metrics {
+        entries: table.len(),
+        characters: shortest.len(),
+        coverage: covered_weight / total_weight,
+        keystrokes: keystrokes / total_weight,
+        full_keystrokes: full_keystrokes / total_weight,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TableMetrics<'a> {
+    table: &'a str,
+    metrics: Metrics,
+}
+
+/// 把计算出的简码连同预定义与额外定义的编码整理成简码表。
+pub fn assignment_table(inputs: &Inputs, assignment: &Assignment, space_jianma: bool) -> Vec<Predefined> {
+    collect_selected_jianma(
+        &assignment.selected,
+        &inputs.predefineds,
+        &inputs.additionals,
+        &inputs.b_area,
+        space_jianma,
+        false,
+    )
+    .into_iter()
+    .map(|(zi, bianma)| Predefined { bianma, zi })
+    .collect()
+}
+
+pub fn write_metrics_header<W: Write>(writer: &mut W) {
+    writeln!(writer, "条目\t单字\t覆盖率\t平均击键\t全码击键\t简码表").unwrap();
+}
+
+pub fn write_metrics_row<W: Write>(writer: &mut W, metrics: &Metrics, label: &str) {
+    writeln!(
+        writer,
+        "{}\t{}\t{:.2}%\t{:.4}\t\t{:.4}\t\t{}",
+        metrics.entries,
+        metrics.characters,
+        metrics.coverage * 100.0,
+        metrics.keystrokes,
+        metrics.full_keystrokes,
+        label,
+    ).unwrap();
+}
+
+pub fn run<W: Write>(writer: W, args: &EvaluateArgs, inputs: &Inputs) {
+    let tables = if args.tables.is_empty() {
+        let assignment = assign_jianma(inputs, args.space_jianma);
+        let table = assignment_table(inputs, &assignment, args.space_jianma);
+
+        vec![(String::from("（计算结果）"), table)]
+    } else {
+        args.tables
+            .iter()
+            .map(|path| (path.display().to_string(), read_jianma_table(path)))
+            .collect()
+    };
+
+    let metrics = tables
+        .iter()
+        .map(|(label, table)| {
+            (label.as_str(), evaluate_table(&inputs.mabiao, table, &inputs.commit))
+        })
+        .collect::<Vec<_>>();
+
+    let mut writer = BufWriter::new(writer);
+    if args.json {
+        let metrics = metrics
+            .into_iter()
+            .map(|(table, metrics)| TableMetrics { table, metrics })
+            .collect::<Vec<_>>();
+        serde_json::to_writer_pretty(&mut writer, &metrics).expect("无法输出评估结果");
+        writeln!(writer).unwrap();
+    } else {
+        write_metrics_header(&mut writer);
+        for (label, metrics) in metrics.iter() {
+            write_metrics_row(&mut writer, metrics, label);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+
+use argh::FromArgs;
+
+use crate::{Candidate, Inputs, Stage, assign_jianma, suffix_jianma_codes};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "explain")]
+/// 解释某些字为何得到（或没有得到）简码。
+pub struct ExplainArgs {
+    #[argh(switch)]
+    /// 允许空格简码。
+    space_jianma: bool,
+
+    #[argh(positional)]
+    /// 要解释的汉字，可以一次给出多个。
+    zi: Vec<String>,
+}
+
+fn stage_name(stage: Stage) -> &'static str {
+    match stage {
+        Stage::Suffix => "韵码简",
+        Stage::Space => "空格简",
+    }
+}
+
+pub fn run<W: Write>(writer: W, args: &ExplainArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+    let assignment = assign_jianma(inputs, args.space_jianma);
+
+    let selected_by_code = assignment.selected
+        .iter()
+        .map(|cand| (cand.bianma.as_str(), cand))
+        .collect::<HashMap<_, _>>();
+
+    let mut candidates_by_zi = HashMap::<&str, Vec<&Candidate>>::new();
+    for cand in assignment.candidates.iter().chain(assignment.space_candidates.iter()) {
+        candidates_by_zi.entry(cand.zi.as_str()).or_default().push(cand);
+    }
+
+    let mut full_code_owners = HashMap::<&str, Vec<char>>::new();
+    for (zi, bianma) in inputs.blockers.iter() {
+        full_code_owners.entry(bianma.as_str()).or_default().push(*zi);
+    }
+
+    for zi in args.zi.iter().flat_map(|zi| zi.chars()) {
+        writeln!(writer, "{zi}").unwrap();
+
+        let Some(character) = inputs.mabiao.get(&zi) else {
+            if let Some(character) = inputs.full_mabiao.get(&zi) {
+                writeln!(writer, "  全码：{}", character.bianma).unwrap();
+                writeln!(writer, "  不在所选的字频表中，因此不参与简码计算。").unwrap();
+            } else {
+                writeln!(writer, "  不在码表中。").unwrap();
+            }
+            writeln!(writer).unwrap();
+            continue
+        };
+
+        let contributions = inputs.corpora
+            .iter()
+            .zip(character.frequencies.iter())
+            .map(|(name, freq)| format!("{name} {freq}"))
+            .collect::<Vec<_>>()
+            .join("，");
+        writeln!(writer, "  全码：{}", character.bianma).unwrap();
+        writeln!(writer, "  字频：{}（{contributions}）", character.weight).unwrap();
+        match inputs.decomposition.get(&zi) {
+            Some(roots) => writeln!(writer, "  字根数：{}（{}）", character.zigen_count, roots.join(" ")).unwrap(),
+            None => writeln!(writer, "  字根数：{}", character.zigen_count).unwrap(),
+        }
+
+        let zi_str = zi.to_string();
+        for pre in inputs.predefineds.iter().filter(|pre| pre.zi == zi_str) {
+            writeln!(writer, "  预定义编码：{}（不参与简码计算）", pre.bianma).unwrap();
+        }
+        for add in inputs.additionals.iter().filter(|add| add.zi == zi_str) {
+            writeln!(writer, "  额外编码：{}", add.bianma).unwrap();
+        }
+
+        for jianma in suffix_jianma_codes(&character.bianma) {
+            if let Some(pattern) = inputs.reserved.matching(&jianma) {
+                writeln!(writer, "  {jianma}\t不可用，为保留编码（{pattern}）").unwrap();
+                continue
+            }
+            if inputs.commit.commits_immediately(&jianma) {
+                let mut shadowed = full_code_owners
+                    .iter()
+                    .filter(|(bianma, _)| bianma.len() > jianma.len() && bianma.starts_with(jianma.as_str()))
+                    .flat_map(|(_, owners)| owners.iter())
+                    .collect::<Vec<_>>();
+                if !shadowed.is_empty() {
+                    shadowed.sort();
+                    shadowed.truncate(5);
+                    let shadowed = shadowed.into_iter().collect::<String>();
+                    writeln!(writer, "  {jianma}\t不可用，打完即上屏，会挡住 {shadowed} 等字的全码").unwrap();
+                    continue
+                }
+            }
+            if inputs.alloweds.contains(&jianma) {
+                continue
+            }
+
+            let mut blockers = full_code_owners
+                .get(jianma.as_str())
+                .map(|owners| owners.iter().map(|zi| zi.to_string()).collect::<Vec<_>>())
+                .unwrap_or_default();
+            blockers.extend(inputs.predefineds
+                .iter()
+                .filter(|pre| pre.bianma == jianma)
+                .map(|pre| format!("{}（预定义）", pre.zi)));
+
+            if !blockers.is_empty() {
+                writeln!(writer, "  {jianma}\t不可用，已是 {} 的编码", blockers.join("、")).unwrap();
+            }
+        }
+
+        let candidates = candidates_by_zi.get(zi_str.as_str()).cloned().unwrap_or_default();
+        if candidates.is_empty() {
+            writeln!(writer, "  没有得分足够高的候选简码。").unwrap();
+        }
+
+        for cand in candidates.iter() {
+            let status = match selected_by_code.get(cand.bianma.as_str()) {
+                Some(winner) if winner.zi == zi_str => String::from("已选中"),
+                Some(winner) => format!("被「{}」占用（得分 {}）", winner.zi, winner.score),
+                None => String::from("未选中"),
+            };
+
+            writeln!(
+                writer,
+                "  {}\t{}\t得分 {} = 字频 × {:.2} × {:.2} × {:.2} × {:.2} × {:.2}\t{status}",
+                cand.bianma,
+                stage_name(cand.stage),
+                cand.score,
+                cand.factors.length,
+                cand.factors.zigen,
+                cand.factors.space,
+                cand.factors.family,
+                cand.factors.typo,
+            ).unwrap();
+        }
+
+        let selected = assignment.selected
+            .iter()
+            .filter(|cand| cand.zi == zi_str)
+            .map(|cand| cand.bianma.as_str())
+            .collect::<Vec<_>>();
+        if selected.is_empty() {
+            writeln!(writer, "  最终简码：无").unwrap();
+        } else {
+            writeln!(writer, "  最终简码：{}", selected.join(" ")).unwrap();
+        }
+        writeln!(writer).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+//! 部件家族：除首个字根外字根序列相同的字（如「清晴请情」共用「青」），多为同一声旁的形声字。
+//! 同一家族的字得到相同模式的简码（同为三码韵码简、同为二码空格简等）时更容易记忆。
+
+use std::collections::HashMap;
+
+use compact_str::CompactString;
+
+use crate::decomposition::Decomposition;
+use crate::{Candidate, Character, Stage};
+
+/// 简码的模式：所属阶段与码长。
+type Template = (Stage, usize);
+
+/// 各字所属的家族与各家族的成员，只保留至少有两个成员的家族。
+#[derive(Debug, Clone, Default)]
+pub struct Families {
+    family_of: HashMap<char, CompactString>,
+    members: HashMap<CompactString, Vec<char>>,
+}
+
+impl Families {
+    /// 由拆分表为参与简码计算的字划分家族。只有一个字根的字不属于任何家族。
+    pub fn new(decomposition: &Decomposition, mabiao: &HashMap<char, Character>) -> Self {
+        let mut members = HashMap::<CompactString, Vec<char>>::new();
+        for zi in mabiao.keys() {
+            let Some(roots) = decomposition.get(zi) else {
+                continue
+            };
+            if roots.len() < 2 {
+                continue
+            }
+            members.entry(CompactString::new(roots[1..].join(" "))).or_default().push(*zi);
+        }
+        members.retain(|_, zis| zis.len() > 1);
+
+        let family_of = members
+            .iter()
+            .flat_map(|(family, zis)| zis.iter().map(move |zi| (*zi, family.clone())))
+            .collect();
+
+        Self { family_of, members }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// 同一家族中的其他字。
+    fn mates(&self, zi: char) -> impl Iterator<Item = char> + '_ {
+        self.family_of
+            .get(&zi)
+            .map(|family| self.members[family].as_slice())
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            .filter(move |mate| *mate != zi)
+    }
+}
+
+fn single_char(zi: &str) -> Option<char> {
+    let mut chars = zi.chars();
+    match (chars.next(), chars.next()) {
+        (Some(zi), None) => Some(zi),
+        _ => None,
+    }
+}
+
+fn templates<'a>(selected: impl IntoIterator<Item = &'a Candidate>) -> HashMap<char, Template> {
+    selected
+        .into_iter()
+        .filter_map(|cand| single_char(&cand.zi).map(|zi| (zi, (cand.stage, cand.bianma.len()))))
+        .collect()
+}
+
+/// 按上一轮的结果更新各候选的家族系数：同家族的其他字中，已得到相同模式简码的字频占比越高，奖励越多。
+pub fn update_family_factor(
+    candidates: &mut [Candidate],
+    assigned: &[&Candidate],
+    families: &Families,
+    mabiao: &HashMap<char, Character>,
+    bonus: f64,
+) {
+    let assigned = templates(assigned.iter().copied());
+    let weight_of = |zi: char| mabiao.get(&zi).map(|ch| ch.weight).unwrap_or(0);
+
+    for cand in candidates.iter_mut() {
+        let Some(zi) = single_char(&cand.zi) else {
+            continue
+        };
+
+        let template = (cand.stage, cand.bianma.len());
+        let (mut same, mut total) = (0, 0);
+        for mate in families.mates(zi) {
+            let weight = weight_of(mate);
+            total += weight;
+            if assigned.get(&mate) == Some(&template) {
+                same += weight;
+            }
+        }
+
+        cand.factors.family = if total == 0 { 1.0 } else { 1.0 + bonus * same as f64 / total as f64 };
+    }
+}
+
+/// 家族一致率：各家族中，与其最常用的简码模式相同的字所占的字频比例。没有简码的字也算作一种模式。
+pub fn consistency(selected: &[Candidate], families: &Families, mabiao: &HashMap<char, Character>) -> f64 {
+    let assigned = templates(selected);
+    let (mut same, mut total) = (0, 0);
+
+    for zis in families.members.values() {
+        let mut by_template = HashMap::<Option<Template>, u64>::new();
+        for zi in zis.iter() {
+            let weight = mabiao.get(zi).map(|ch| ch.weight).unwrap_or(0);
+            *by_template.entry(assigned.get(zi).copied()).or_default() += weight;
+            total += weight;
+        }
+        same += by_template.values().max().copied().unwrap_or(0);
+    }
+
+    if total == 0 { 1.0 } else { same as f64 / total as f64 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Factors;
+
+    fn decomposition() -> Decomposition {
+        [('清', "氵 青"), ('晴', "日 青"), ('请', "讠 青"), ('情', "忄 青"), ('河', "氵 可"), ('青', "青")]
+            .into_iter()
+            .map(|(zi, roots)| (zi, roots.split(' ').map(CompactString::from).collect()))
+            .collect()
+    }
+
+    fn mabiao() -> HashMap<char, Character> {
+        [('清', 40), ('晴', 30), ('请', 20), ('情', 10), ('河', 50), ('青', 60)]
+            .into_iter()
+            .map(|(zi, weight)| (zi, Character { bianma: CompactString::from("aaaa"), weight, zigen_count: 2, frequencies: vec![weight] }))
+            .collect()
+    }
+
+    fn candidate(zi: &str, bianma: &str, stage: Stage) -> Candidate {
+        Candidate {
+            zi: CompactString::from(zi),
+            bianma: CompactString::from(bianma),
+            frequency: 1,
+            stage,
+            factors: Factors { length: 1.0, zigen: 1.0, space: 1.0, family: 1.0, typo: 1.0 },
+            score: 1,
+        }
+    }
+
+    #[test]
+    fn families_share_all_but_the_first_root() {
+        let mut mabiao = mabiao();
+        mabiao.remove(&'情');
+        let families = Families::new(&decomposition(), &mabiao);
+
+        // 河 的家族只有它自己，青 只有一个字根，情 不参与计算。
+        assert_eq!(families.members.len(), 1);
+        let mut mates = families.mates('清').collect::<Vec<_>>();
+        mates.sort();
+        assert_eq!(mates, vec!['晴', '请']);
+        assert_eq!(families.mates('河').count(), 0);
+        assert_eq!(families.mates('青').count(), 0);
+        assert!(Families::new(&Decomposition::new(), &mabiao).is_empty());
+    }
+
+    #[test]
+    fn bonus_by_share_of_matching_templates() {
+        let mabiao = mabiao();
+        let families = Families::new(&decomposition(), &mabiao);
+        let assigned = [candidate("晴", "qa", Stage::Suffix), candidate("请", "qe", Stage::Space)];
+        let assigned = assigned.iter().collect::<Vec<_>>();
+
+        let mut candidates = vec![
+            candidate("清", "qi", Stage::Suffix),
+            candidate("清", "qio", Stage::Suffix),
+            candidate("河", "ho", Stage::Suffix),
+            candidate("清晴", "qq", Stage::Suffix),
+        ];
+        update_family_factor(&mut candidates, &assigned, &families, &mabiao, 0.6);
+
+        // 清 的同家族字频合计 60，其中 晴（30）的模式为两码韵码简。
+        assert_eq!(candidates[0].factors.family, 1.3);
+        assert_eq!(candidates[1].factors.family, 1.0);
+        assert_eq!(candidates[2].factors.family, 1.0);
+        assert_eq!(candidates[3].factors.family, 1.0);
+    }
+
+    #[test]
+    fn consistency_counts_the_most_common_template() {
+        let mabiao = mabiao();
+        let families = Families::new(&decomposition(), &mabiao);
+        assert_eq!(consistency(&[], &families, &mabiao), 1.0);
+
+        // 清、晴 为两码韵码简（70），请 为两码空格简，情 没有简码，家族字频合计 100。
+        let selected = [
+            candidate("清", "qi", Stage::Suffix),
+            candidate("晴", "qa", Stage::Suffix),
+            candidate("请", "qe", Stage::Space),
+        ];
+        assert_eq!(consistency(&selected, &families, &mabiao), 0.7);
+        assert_eq!(consistency(&selected, &Families::default(), &mabiao), 1.0);
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use argh::FromArgs;
+use compact_str::CompactString;
+
+use crate::Inputs;
+use crate::frequency::open_reader;
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "freq")]
+/// 字频表相关的工具。
+pub struct FreqArgs {
+    #[argh(subcommand)]
+    command: FreqCommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum FreqCommand {
+    Build(FreqBuildArgs),
+    Share(FreqShareArgs),
+}
+
+/// 统计时保留的字符范围。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// CJK 统一汉字及各扩展区、兼容汉字与〇。
+    Cjk,
+    /// 只保留码表中的字。
+    Mabiao,
+    /// 除空白与控制字符以外的所有字符。
+    All,
+}
+
+impl FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cjk" => Ok(Self::Cjk),
+            "mabiao" => Ok(Self::Mabiao),
+            "all" => Ok(Self::All),
+            _ => Err(format!("未知的字符范围：{s}")),
+        }
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "build")]
+/// 由纯文本或 JSONL 语料统计字频，输出可直接用于 --frequency 的字频表。
+pub struct FreqBuildArgs {
+    #[argh(option)]
+    /// 字频表输出路径。以 .json 结尾时输出 JSON，否则输出 TSV。
+    out: PathBuf,
+
+    #[argh(option)]
+    /// 词频表输出路径（TSV，可用于 --words）。指定后同时统计 2 至 --ngram 字的片段。
+    words_out: Option<PathBuf>,
+
+    #[argh(option, default = "2")]
+    /// 统计词频时最长的片段字数。默认为 2。
+    ngram: usize,
+
+    #[argh(option, default = "Charset::Cjk")]
+    /// 保留的字符范围：cjk（汉字）、mabiao（码表中的字）或 all（除空白与控制字符外的所有字符）。
+    /// 范围以外的字符不计入字频，并会截断词组片段。默认为 cjk。
+    charset: Charset,
+
+    #[argh(option, default = r#"String::from("text")"#)]
+    /// JSONL 语料中存放正文的字段。默认为 text。
+    field: String,
+
+    #[argh(option, default = "1")]
+    /// 输出时略去次数低于此值的条目。默认为 1。
+    min_count: u64,
+
+    #[argh(option, default = "5000000")]
+    /// 词组片段最多同时保留的条目数。超出时舍去低频片段，使长语料的内存占用有上限。默认为 5000000。
+    max_entries: usize,
+
+    #[argh(positional)]
+    /// 语料路径。以 .jsonl 结尾的文件按 JSONL 读取，以 .gz 结尾的文件会自动解压。
+    corpora: Vec<PathBuf>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "share")]
+/// 报告各字频表在混合后的字频中实际所占的比例。
+pub struct FreqShareArgs {
+    #[argh(option, default = "0")]
+    /// 只统计混合后字频最高的若干字，0 表示统计所有字。默认为 0。
+    top: usize,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3007}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2EBEF}'
+        | '\u{2F800}'..='\u{2FA1F}'
+        | '\u{30000}'..='\u{323AF}'
+    )
+}
+
+/// 边读边统计的计数器。字的种类有限，全部保留；词组片段超出上限时舍去低频条目。
+struct Counter {
+    chars: HashMap<char, u64>,
+    ngrams: HashMap<CompactString, u64>,
+    ngram: usize,
+    max_entries: usize,
+    /// 舍去片段时使用的次数下限，每次舍去后提高，次数不超过它的片段都会被舍去。
+    prune_floor: u64,
+    pruned: u64,
+}
+
+impl Counter {
+    fn new(ngram: usize, max_entries: usize) -> Self {
+        Self {
+            chars: HashMap::new(),
+            ngrams: HashMap::new(),
+            ngram,
+            max_entries: max_entries.max(1),
+            prune_floor: 0,
+            pruned: 0,
+        }
+    }
+
+    /// 统计一段连续的、都在字符范围以内的字。
+    fn add_run(&mut self, run: &[char]) {
+        for zi in run.iter() {
+            *self.chars.entry(*zi).or_default() += 1;
+        }
+
+        for n in 2..=self.ngram {
+            for window in run.windows(n) {
+                let word = window.iter().collect::<CompactString>();
+                *self.ngrams.entry(word).or_default() += 1;
+            }
+        }
+
+        if self.ngrams.len() > self.max_entries {
+            self.prune();
+        }
+    }
+
+    fn prune(&mut self) {
+        while self.ngrams.len() > self.max_entries / 2 {
+            self.prune_floor += 1;
+            let before = self.ngrams.len();
+            self.ngrams.retain(|_, count| *count > self.prune_floor);
+            self.pruned += (before - self.ngrams.len()) as u64;
+        }
+    }
+}
+
+fn count_text(counter: &mut Counter, text: &str, keep: &impl Fn(char) -> bool, run: &mut Vec<char>) {
+    run.clear();
+    for c in text.chars() {
+        if keep(c) {
+            run.push(c);
+        } else if !run.is_empty() {
+            counter.add_run(run);
+            run.clear();
+        }
+    }
+
+    if !run.is_empty() {
+        counter.add_run(run);
+    }
+}
+
+fn is_jsonl(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    name.ends_with(".jsonl")
+}
+
+fn count_corpus(counter: &mut Counter, path: &Path, field: &str, keep: &impl Fn(char) -> bool) {
+    let reader = open_reader(path, "语料");
+    let jsonl = is_jsonl(path);
+    let mut run = Vec::new();
+    let mut invalid = 0;
+
+    for line in reader.lines() {
+        let line = line.unwrap_or_else(|_| panic!("无法读取语料 {}", path.display()));
+
+        if !jsonl {
+            count_text(counter, &line, keep, &mut run);
+            continue
+        }
+
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let text = serde_json::from_str::<serde_json::Value>(&line)
+            .ok()
+            .and_then(|value| value.get(field).and_then(|text| text.as_str()).map(String::from));
+        match text {
+            Some(text) => count_text(counter, &text, keep, &mut run),
+            None => invalid += 1,
+        }
+    }
+
+    if invalid > 0 {
+        eprintln!("警告：语料 {} 中有 {invalid} 行无法解析或缺少 {field} 字段，已被略过", path.display());
+    }
+}
+
+fn sorted_counts<K: Ord>(counts: impl IntoIterator<Item = (K, u64)>, min_count: u64) -> Vec<(K, u64)> {
+    let mut counts = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= min_count)
+        .collect::<Vec<_>>();
+    counts.sort_by(|a, b| a.1.cmp(&b.1).reverse().then(a.0.cmp(&b.0)));
+    counts
+}
+
+fn write_char_table(path: &Path, counts: &[(char, u64)]) {
+    let file = File::create(path).expect("无法创建字频表文件");
+    let mut writer = BufWriter::new(file);
+    let json = path.extension().is_some_and(|ext| ext == "json");
+
+    if json {
+        writeln!(writer, "{{").unwrap();
+        for (i, (zi, count)) in counts.iter().enumerate() {
+            let key = serde_json::to_string(&zi.to_string()).unwrap();
+            let comma = if i + 1 < counts.len() { "," } else { "" };
+            writeln!(writer, "  {key}: {count}{comma}").unwrap();
+        }
+        writeln!(writer, "}}").unwrap();
+    } else {
+        for (zi, count) in counts.iter() {
+            writeln!(writer, "{zi}\t{count}").unwrap();
+        }
+    }
+}
+
+fn write_word_table(path: &Path, counts: &[(CompactString, u64)]) {
+    let file = File::create(path).expect("无法创建词频表文件");
+    let mut writer = BufWriter::new(file);
+
+    for (word, count) in counts.iter() {
+        writeln!(writer, "{word}\t{count}").unwrap();
+    }
+}
+
+/// mabiao 为码表中的字，只在 --charset mabiao 时用到；inputs 只在 share 中用到。
+pub fn run<W: Write>(
+    writer: W,
+    args: &FreqArgs,
+    mabiao: impl FnOnce() -> HashSet<char>,
+    inputs: impl FnOnce() -> Inputs,
+) {
+    match &args.command {
+        FreqCommand::Build(args) => run_build(args, mabiao),
+        FreqCommand::Share(args) => run_share(writer, args, &inputs()),
+    }
+}
+
+fn run_share<W: Write>(writer: W, args: &FreqShareArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+    let mut characters = inputs.mabiao.iter().collect::<Vec<_>>();
+    characters.sort_by(|a, b| a.1.weight.cmp(&b.1.weight).reverse().then(a.0.cmp(b.0)));
+    if args.top > 0 {
+        characters.truncate(args.top);
+    }
+
+    let total = characters.iter().map(|(_, ch)| ch.weight).sum::<u64>();
+
+    writeln!(writer, "统计 {} 字，混合后字频合计 {total}", characters.len()).unwrap();
+    writeln!(writer, "字频表\t字数\t贡献\t\t占比").unwrap();
+    for (i, name) in inputs.corpora.iter().enumerate() {
+        let contribution = characters.iter().map(|(_, ch)| ch.frequencies[i]).sum::<u64>();
+        let count = characters.iter().filter(|(_, ch)| ch.frequencies[i] > 0).count();
+        let share = if total > 0 { contribution as f64 / total as f64 } else { 0.0 };
+
+        writeln!(writer, "{name}\t{count}\t{contribution}\t{:.2}%", share * 100.0).unwrap();
+    }
+}
+
+fn run_build(args: &FreqBuildArgs, mabiao: impl FnOnce() -> HashSet<char>) {
+    let ngram = if args.words_out.is_some() { args.ngram } else { 1 };
+    let mut counter = Counter::new(ngram, args.max_entries);
+
+    let mabiao = if args.charset == Charset::Mabiao { mabiao() } else { HashSet::new() };
+    let keep = |c: char| match args.charset {
+        Charset::Cjk => is_cjk(c),
+        Charset::Mabiao => mabiao.contains(&c),
+        Charset::All => !c.is_whitespace() && !c.is_control(),
+    };
+
+    for path in args.corpora.iter() {
+        count_corpus(&mut counter, path, &args.field, &keep);
+    }
+
+    let chars = sorted_counts(counter.chars, args.min_count);
+    let total = chars.iter().map(|(_, count)| count).sum::<u64>();
+    write_char_table(&args.out, &chars);
+    println!("字频表：{} 字，共 {total} 次", chars.len());
+
+    if let Some(words_out) = &args.words_out {
+        let words = sorted_counts(counter.ngrams, args.min_count.max(counter.prune_floor + 1));
+        write_word_table(words_out, &words);
+        println!("词频表：{} 条", words.len());
+
+        if counter.pruned > 0 {
+            println!(
+                "为控制内存舍去了 {} 个低频片段，次数不超过 {} 的片段不可靠，已一并略去",
+                counter.pruned, counter.prune_floor,
+            );
+        }
+    }
+}
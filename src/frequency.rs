@@ -0,0 +1,530 @@
+//! 读取字频表。`--frequency` 中的每一项可以是 `frequency/` 目录下的字频表名称，也可以是文件路径，
+//! 支持 JSON（`{"字": 次数}`）、TSV（`字\t次数`）与 CSV（`字,次数`）格式，以及 gzip 压缩的文件。
+//! TSV 与 CSV 中以 # 开头的行为注释，`#\t次数` 这样 # 紧接分隔符的行仍是 # 的字频。
+//! 字频表还可以先经 OpenCC 格式的转换表转换（如繁转简），再参与混合。
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use compact_str::CompactString;
+use flate2::read::MultiGzDecoder;
+
+/// `--frequency` 中的一项。
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencySource {
+    /// 字频表的名称，即文件名去掉扩展名后的部分。
+    pub name: CompactString,
+    pub path: PathBuf,
+    pub weight: f64,
+    /// 依次使用的转换表。
+    pub conversions: Vec<PathBuf>,
+}
+
+/// 混合前对每张字频表的归一化方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// 原样使用次数。
+    None,
+    /// 按总次数归一，即按每百万字的次数比较。数值换算到 NORMALIZED_TOTAL 的规模，与每百万字的次数只差一个常数倍。
+    PerMillion,
+    /// 只看名次，第 r 名取 1/r。
+    Rank,
+    /// 取 ln(1 + 次数)，压低高频字的优势。
+    Log,
+    /// 按名次拟合 Zipf 分布，以拟合值代替次数，抹平次数的噪声。rank 相当于指数恒为 1 的 zipf。
+    Zipf,
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            // per-billion 为旧的写法，与 per-million 相同。
+            "per-million" | "per-billion" => Ok(Self::PerMillion),
+            "rank" => Ok(Self::Rank),
+            "log" => Ok(Self::Log),
+            "zipf" => Ok(Self::Zipf),
+            _ => Err(format!("未知的归一化方式：{s}")),
+        }
+    }
+}
+
+/// 归一化后每张字频表的总次数，与自带的字频表（约十亿字）同一规模，
+/// 使简码计算中的得分阈值依然适用。
+const NORMALIZED_TOTAL: f64 = 1e9;
+
+/// 按次数从高到低排出名次，次数相同的字名次相同。
+fn ranked(table: &HashMap<char, f64>) -> Vec<(char, usize)> {
+    let mut entries = table.iter().map(|(zi, freq)| (*zi, *freq)).collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut result = Vec::with_capacity(entries.len());
+    let mut rank = 0;
+    for (i, (zi, freq)) in entries.iter().enumerate() {
+        if i == 0 || *freq != entries[i - 1].1 {
+            rank = i + 1;
+        }
+        result.push((*zi, rank));
+    }
+    result
+}
+
+/// 拟合 Zipf 分布时只用累计占总次数这一比例以内的高频字，低频字的次数太少，不足为凭。
+const ZIPF_FIT_MASS: f64 = 0.99;
+
+/// 以最小二乘法拟合 ln(次数) = c - s·ln(名次)，返回 s。
+fn zipf_exponent(table: &HashMap<char, f64>, ranks: &[(char, usize)]) -> f64 {
+    let total = table.values().sum::<f64>();
+    let mut cumulative = 0.0;
+    let points = ranks
+        .iter()
+        .take_while(|(zi, _)| {
+            let within = cumulative < total * ZIPF_FIT_MASS;
+            cumulative += table[zi];
+            within
+        })
+        .filter(|(zi, _)| table[zi] > 0.0)
+        .map(|(zi, rank)| ((*rank as f64).ln(), table[zi].ln()))
+        .collect::<Vec<_>>();
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return 1.0;
+    }
+
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxy = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum::<f64>();
+    let sxx = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum::<f64>();
+
+    if sxx > 0.0 { -sxy / sxx } else { 1.0 }
+}
+
+/// 对一张字频表做归一化。除 None 以外，归一化后的总次数为 NORMALIZED_TOTAL。
+pub fn normalize(table: &mut HashMap<char, f64>, mode: Normalization) {
+    match mode {
+        Normalization::None => return,
+        Normalization::PerMillion => {}
+        Normalization::Log => {
+            for freq in table.values_mut() {
+                *freq = freq.ln_1p();
+            }
+        }
+        Normalization::Rank | Normalization::Zipf => {
+            let ranks = ranked(table);
+            let exponent = if mode == Normalization::Zipf {
+                zipf_exponent(table, &ranks)
+            } else {
+                1.0
+            };
+
+            for (zi, rank) in ranks {
+                table.insert(zi, (rank as f64).powf(-exponent));
+            }
+        }
+    }
+
+    let total = table.values().sum::<f64>();
+    if total > 0.0 {
+        for freq in table.values_mut() {
+            *freq *= NORMALIZED_TOTAL / total;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Tsv,
+    Csv,
+}
+
+/// 解析 `--frequency`，如 `beiyu:0.5,zhihu:0.5,corpus/chat.tsv.gz:2,tw@opencc/TSCharacters.txt:0.01`。
+/// 不含路径分隔符与扩展名的项视作 `frequency/{名称}.json`；权重省略时为 1.0；
+/// `@` 之后为转换表的路径，多个转换表用 `+` 连接，依次使用。
+pub fn parse_sources(frequency: &str) -> Vec<FrequencySource> {
+    frequency
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (spec, weight) = match item.rsplit_once(':') {
+                Some((spec, weight)) => {
+                    let weight = weight
+                        .parse::<f64>()
+                        .unwrap_or_else(|_| panic!("字频表 {item} 的权重无效"));
+                    (spec, weight)
+                }
+                None => (item, 1.0),
+            };
+
+            let (spec, conversions) = match spec.split_once('@') {
+                Some((spec, conversions)) => {
+                    let conversions = conversions.split('+').map(PathBuf::from).collect();
+                    (spec, conversions)
+                }
+                None => (spec, Vec::new()),
+            };
+
+            let is_path = spec.contains(['/', '\\']) || Path::new(spec).extension().is_some();
+            let path = if is_path {
+                PathBuf::from(spec)
+            } else {
+                PathBuf::from(format!("frequency/{spec}.json"))
+            };
+
+            FrequencySource {
+                name: CompactString::from(table_name(&path)),
+                path,
+                weight,
+                conversions,
+            }
+        })
+        .collect()
+}
+
+/// 去掉 .gz 及格式扩展名后的文件名。
+fn table_name(path: &Path) -> &str {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name)
+}
+
+fn format_of(path: &Path) -> Format {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+
+    match name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()) {
+        Some(ext) if ext == "json" => Format::Json,
+        Some(ext) if ext == "csv" => Format::Csv,
+        _ => Format::Tsv,
+    }
+}
+
+/// 打开文件，以 .gz 结尾时自动解压。
+pub fn open_reader(path: &Path, name: &str) -> Box<dyn BufRead> {
+    let file = File::open(path).unwrap_or_else(|_| panic!("无法打开{name} {}", path.display()));
+
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    }
+}
+
+/// 解析字频：整数或小数原样使用，百分数换算为每百万字的次数。
+fn parse_value(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let result = match value.strip_suffix('%') {
+        Some(percentage) => percentage.trim().parse::<f64>().ok()? * 10000.0,
+        None => value.parse::<f64>().ok()?,
+    };
+
+    (result.is_finite() && result >= 0.0).then_some(result)
+}
+
+/// 记录被略过的键，读完后统一提示。
+#[derive(Default)]
+struct Skipped {
+    count: usize,
+    examples: Vec<String>,
+}
+
+impl Skipped {
+    fn add(&mut self, key: &str) {
+        self.count += 1;
+        if self.examples.len() < 5 {
+            self.examples.push(key.to_string());
+        }
+    }
+
+    fn warn(&self, source: &FrequencySource, what: &str) {
+        if self.count > 0 {
+            eprintln!(
+                "警告：字频表 {} 中有 {} 个{what}已被略过，如 {}",
+                source.name,
+                self.count,
+                self.examples.join("、"),
+            );
+        }
+    }
+}
+
+fn single_char(key: &str) -> Option<char> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(zi), None) => Some(zi),
+        _ => None,
+    }
+}
+
+/// 以 # 开头的行为注释，但 # 紧接分隔符时是以 # 为键的条目。行中其他位置的 # 不是注释。
+fn is_comment(line: &str, separator: char) -> bool {
+    line.strip_prefix('#').is_some_and(|rest| !rest.starts_with(separator))
+}
+
+/// 读取一张字频表。不是单个字符的键（如词组或组合字符序列）与无效的字频会被略过并提示。
+pub fn read_frequency_table(source: &FrequencySource) -> HashMap<char, f64> {
+    let mut reader = open_reader(&source.path, "字频表");
+    let mut result = HashMap::<char, f64>::new();
+    let mut multi_char = Skipped::default();
+    let mut invalid = Skipped::default();
+
+    match format_of(&source.path) {
+        Format::Json => {
+            let mut content = String::new();
+            reader
+                .read_to_string(&mut content)
+                .unwrap_or_else(|_| panic!("无法读取字频表 {}", source.name));
+
+            let json: HashMap<String, serde_json::Value> = serde_json::from_str(&content)
+                .unwrap_or_else(|_| panic!("无法解析字频表JSON {}", source.name));
+
+            for (key, value) in json.iter() {
+                let Some(zi) = single_char(key) else {
+                    multi_char.add(key);
+                    continue
+                };
+
+                let freq = match value {
+                    serde_json::Value::Number(n) => n.as_f64().filter(|f| *f >= 0.0),
+                    serde_json::Value::String(s) => parse_value(s),
+                    _ => None,
+                };
+                match freq {
+                    Some(freq) => *result.entry(zi).or_default() += freq,
+                    None => invalid.add(key),
+                }
+            }
+        }
+        format => {
+            let separator = if format == Format::Csv { ',' } else { '\t' };
+
+            for (i, line) in reader.lines().enumerate() {
+                let line = line.unwrap_or_else(|_| panic!("无法读取字频表 {}", source.name));
+                let line = line.trim_ascii();
+
+                if line.is_empty() || is_comment(line, separator) {
+                    continue
+                }
+
+                let Some((key, value)) = line.split_once(separator) else {
+                    panic!("字频表 {} 第{}行存在错误", source.name, i + 1);
+                };
+                let key = key.trim();
+                let value = value.split(separator).next().unwrap_or(value);
+
+                let Some(zi) = single_char(key) else {
+                    multi_char.add(key);
+                    continue
+                };
+                match parse_value(value) {
+                    Some(freq) => *result.entry(zi).or_default() += freq,
+                    None => invalid.add(key),
+                }
+            }
+        }
+    }
+
+    multi_char.warn(source, "不是单字的键");
+    invalid.warn(source, "字频无效的条目");
+    result
+}
+
+/// OpenCC 格式的转换表：一行一个字与转换后的字（用 \t 隔开），转换结果有多个时用空格隔开。
+pub struct Conversion {
+    map: HashMap<char, Vec<char>>,
+}
+
+/// 读取转换表。OpenCC 的转换表中也有词组，词组条目会被略过。与字频表相同，只有行首的 # 表示注释。
+pub fn read_conversion(path: &Path) -> Conversion {
+    let reader = open_reader(path, "转换表");
+    let mut map = HashMap::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.unwrap_or_else(|_| panic!("无法读取转换表 {}", path.display()));
+        let line = line.trim_ascii();
+
+        if line.is_empty() || is_comment(line, '\t') {
+            continue
+        }
+
+        let Some((from, to)) = line.split_once('\t') else {
+            panic!("转换表 {} 第{}行存在错误", path.display(), i + 1);
+        };
+
+        let Some(from) = single_char(from) else {
+            continue
+        };
+        let to = to.split_whitespace().filter_map(single_char).collect::<Vec<_>>();
+        if !to.is_empty() {
+            map.insert(from, to);
+        }
+    }
+
+    Conversion { map }
+}
+
+impl Conversion {
+    /// 按转换表转换一张字频表，不在转换表中的字保持不变。一个字对应多个字时，
+    /// 按各个字在 reference 中的字频比例分配次数；都不在 reference 中时平均分配。
+    pub fn convert(&self, table: &HashMap<char, f64>, reference: &HashMap<char, f64>) -> HashMap<char, f64> {
+        let mut result = HashMap::<char, f64>::new();
+
+        for (zi, freq) in table.iter() {
+            let Some(targets) = self.map.get(zi) else {
+                *result.entry(*zi).or_default() += freq;
+                continue
+            };
+
+            let total = targets.iter().map(|target| reference.get(target).copied().unwrap_or(0.0)).sum::<f64>();
+            for target in targets.iter() {
+                let share = if total > 0.0 {
+                    reference.get(target).copied().unwrap_or(0.0) / total
+                } else {
+                    1.0 / targets.len() as f64
+                };
+                *result.entry(*target).or_default() += freq * share;
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sources_names_and_weights() {
+        let sources = parse_sources("beiyu:0.5, zhihu ,corpus/chat.tsv.gz:2,,");
+        assert_eq!(sources.len(), 3);
+
+        assert_eq!(sources[0].name, "beiyu");
+        assert_eq!(sources[0].path, PathBuf::from("frequency/beiyu.json"));
+        assert_eq!(sources[0].weight, 0.5);
+
+        assert_eq!(sources[1].path, PathBuf::from("frequency/zhihu.json"));
+        assert_eq!(sources[1].weight, 1.0);
+
+        assert_eq!(sources[2].name, "chat");
+        assert_eq!(sources[2].path, PathBuf::from("corpus/chat.tsv.gz"));
+        assert_eq!(sources[2].weight, 2.0);
+    }
+
+    #[test]
+    fn parse_sources_treats_extensions_as_paths() {
+        let sources = parse_sources("words.csv:3");
+        assert_eq!(sources[0].path, PathBuf::from("words.csv"));
+        assert_eq!(sources[0].name, "words");
+    }
+
+    #[test]
+    fn parse_sources_conversions() {
+        let sources = parse_sources("tw@opencc/TSCharacters.txt+opencc/extra.txt:0.01,hk@a.txt");
+        assert_eq!(sources[0].name, "tw");
+        assert_eq!(sources[0].weight, 0.01);
+        assert_eq!(
+            sources[0].conversions,
+            vec![PathBuf::from("opencc/TSCharacters.txt"), PathBuf::from("opencc/extra.txt")],
+        );
+        assert_eq!(sources[1].conversions, vec![PathBuf::from("a.txt")]);
+        assert_eq!(sources[1].weight, 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "权重无效")]
+    fn parse_sources_rejects_bad_weight() {
+        parse_sources("beiyu:half");
+    }
+
+    #[test]
+    fn parse_value_numbers_and_percentages() {
+        assert_eq!(parse_value("42"), Some(42.0));
+        assert_eq!(parse_value(" 1.5 "), Some(1.5));
+        assert_eq!(parse_value("0.52%"), Some(5200.0));
+        assert_eq!(parse_value("1 %"), Some(10000.0));
+        assert_eq!(parse_value("-1"), None);
+        assert_eq!(parse_value("abc"), None);
+        assert_eq!(parse_value("inf"), None);
+        assert_eq!(parse_value("%"), None);
+    }
+
+    #[test]
+    fn per_million_normalization() {
+        assert_eq!("per-million".parse::<Normalization>(), Ok(Normalization::PerMillion));
+        assert_eq!("per-billion".parse::<Normalization>(), Ok(Normalization::PerMillion));
+        assert!("per-thousand".parse::<Normalization>().is_err());
+
+        let mut table = HashMap::from([('的', 3.0), ('了', 1.0)]);
+        normalize(&mut table, Normalization::PerMillion);
+        assert_eq!(table[&'的'], NORMALIZED_TOTAL * 0.75);
+        assert_eq!(table[&'了'], NORMALIZED_TOTAL * 0.25);
+    }
+
+    #[test]
+    fn comments_only_at_line_start() {
+        assert!(is_comment("# 注释", '\t'));
+        assert!(is_comment("#注释", ','));
+        assert!(!is_comment("#\t12", '\t'));
+        assert!(!is_comment("#,12", ','));
+        assert!(!is_comment("的\t12", '\t'));
+    }
+
+    #[test]
+    fn read_conversion_keeps_hash_entries() {
+        let path = std::env::temp_dir().join(format!("makejian-{}-conversion.txt", std::process::id()));
+        std::fs::write(&path, concat!(
+            "# 注释\n",
+            "們\t们\n",
+            "＃\t#\n",
+            "#\t＃\n",
+            "發\t发 髮 #\n",
+            "\n",
+            "乾燥\t干燥\n",
+        )).unwrap();
+        let conversion = read_conversion(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(conversion.map.len(), 4);
+        assert_eq!(conversion.map[&'們'], vec!['们']);
+        assert_eq!(conversion.map[&'＃'], vec!['#']);
+        assert_eq!(conversion.map[&'#'], vec!['＃']);
+        assert_eq!(conversion.map[&'發'], vec!['发', '髮', '#']);
+    }
+
+    #[test]
+    fn convert_keeps_unmapped_and_merges_one_to_one() {
+        let conversion = Conversion { map: HashMap::from([('們', vec!['们'])]) };
+        let table = HashMap::from([('們', 10.0), ('们', 5.0), ('的', 7.0)]);
+
+        let result = conversion.convert(&table, &HashMap::new());
+        assert_eq!(result, HashMap::from([('们', 15.0), ('的', 7.0)]));
+    }
+
+    #[test]
+    fn convert_splits_by_reference() {
+        let conversion = Conversion { map: HashMap::from([('發', vec!['发', '髮'])]) };
+        let table = HashMap::from([('發', 100.0)]);
+        let reference = HashMap::from([('发', 3.0), ('髮', 1.0)]);
+
+        let result = conversion.convert(&table, &reference);
+        assert_eq!(result[&'发'], 75.0);
+        assert_eq!(result[&'髮'], 25.0);
+    }
+
+    #[test]
+    fn convert_splits_evenly_without_reference() {
+        let conversion = Conversion { map: HashMap::from([('發', vec!['发', '髮'])]) };
+        let table = HashMap::from([('發', 100.0)]);
+
+        let result = conversion.convert(&table, &HashMap::from([('的', 1.0)]));
+        assert_eq!(result[&'发'], 50.0);
+        assert_eq!(result[&'髮'], 50.0);
+    }
+}
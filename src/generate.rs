@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{Write, stdout};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use argh::FromArgs;
+
+use crate::{
+    Inputs, assign_jianma, family, collect_selected_jianma, make_all_jianma_candidate, output,
+    sort_candidates, with_scoring_args, write_jianma_candidate, write_selected_jianma,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Tsv,
+    Rime,
+    Libime,
+    Ibus,
+    Json,
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tsv" => Ok(Self::Tsv),
+            "rime" => Ok(Self::Rime),
+            "libime" => Ok(Self::Libime),
+            "ibus" => Ok(Self::Ibus),
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => Err(format!("未知的输出格式：{s}")),
+        }
+    }
+}
+
+/// 候选简码的输出格式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidatesFormat {
+    Tsv,
+    Json,
+    Jsonl,
+}
+
+impl FromStr for CandidatesFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tsv" => Ok(Self::Tsv),
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => Err(format!("未知的候选简码输出格式：{s}，只能是 tsv、json 或 jsonl")),
+        }
+    }
+}
+
+with_scoring_args! {
+#[derive(FromArgs)]
+#[argh(subcommand, name = "generate")]
+/// 计算简码并输出简码表。
+pub struct GenerateArgs {
+    #[argh(option, default=r#"PathBuf::from("output/yuming.txt")"#)]
+    /// 简码表输出路径。
+    out: PathBuf,
+
+    #[argh(switch)]
+    /// 允许空格简码。
+    space_jianma: bool,
+
+    #[argh(switch)]
+    /// 按频率排序生成出来的简码表。
+    sort_freq: bool,
+
+    #[argh(option, default="OutputFormat::Tsv")]
+    /// 简码表输出格式：tsv（一行一个汉字与编码）、rime（Rime 的 dict.yaml）、
+    /// libime（fcitx5-table 的文本码表）、ibus（ibus-table 的码表源文件）、
+    /// json 或 jsonl。默认为 tsv。
+    format: OutputFormat,
+
+    #[argh(option, default="String::new()")]
+    /// 输出码表的名称，默认取输出文件名（去掉 .dict.yaml）。
+    dict_name: String,
+
+    #[argh(option, default=r#"String::from("1.0")"#)]
+    /// 输出码表的版本，默认为 1.0。
+    dict_version: String,
+
+    #[argh(option, default=r#"String::from("by_weight")"#)]
+    /// 输出 Rime 码表时的排序方式（by_weight 或 original），默认为 by_weight。
+    rime_sort: String,
+
+    #[argh(option, default="String::new()")]
+    /// 输出 Rime 码表时的 import_tables，多个码表用逗号隔开。
+    rime_import: String,
+
+    #[argh(switch)]
+    /// 把码表中所有字的全码并入输出的码表，使之成为完整可用的码表。
+    merge_mabiao: bool,
+}
+}
+
+with_scoring_args! {
+#[derive(FromArgs)]
+#[argh(subcommand, name = "candidates")]
+/// 打印候选简码。
+pub struct CandidatesArgs {
+    #[argh(option, default="CandidatesFormat::Tsv")]
+    /// 输出格式：tsv、json 或 jsonl。默认为 tsv。
+    format: CandidatesFormat,
+
+    #[argh(option)]
+    /// 输出路径，默认输出到标准输出。
+    out: Option<PathBuf>,
+}
+}
+
+pub fn run_generate(args: &GenerateArgs, inputs: &Inputs) {
+    // 先检查输出参数，免得算完简码才发现无法输出。
+    let mut options = output::DictOptions::new(&args.out, &args.dict_name, &args.dict_version, &args.rime_import);
+    if args.format == OutputFormat::Rime {
+        options = options.with_rime_sort(&args.rime_sort).unwrap_or_else(|err| {
+            eprintln!("错误：{err}");
+            std::process::exit(1);
+        });
+    }
+
+    let assignment = assign_jianma(inputs, args.space_jianma);
+
+    let selected_entries = collect_selected_jianma(
+        &assignment.selected,
+        &inputs.predefineds,
+        &inputs.additionals,
+        &inputs.b_area,
+        args.space_jianma,
+        args.sort_freq,
+    );
+
+    let out = File::create(&args.out).expect("无法创建简码表文件");
+    if matches!(args.format, OutputFormat::Json | OutputFormat::Jsonl) {
+        let records = output::selected_records(
+            &selected_entries,
+            &assignment.selected,
+            &inputs.predefineds,
+            &inputs.mabiao,
+            &inputs.corpora,
+            &inputs.b_area,
+        );
+        output::write_json_records(out, &records, args.format == OutputFormat::Jsonl);
+    } else if args.format == OutputFormat::Tsv && !args.merge_mabiao {
+        write_selected_jianma(out, &selected_entries);
+    } else {
+        let mut entries = output::weighted_entries(&selected_entries, &inputs.mabiao);
+        if args.merge_mabiao {
+            output::merge_full_codes(&mut entries, &inputs.full_mabiao, &inputs.mabiao);
+        }
+
+        let rules = inputs.word_rule.table_rules();
+        let code_keys = inputs.layout.code_keys();
+
+        match args.format {
+            OutputFormat::Tsv => {
+                let entries = entries
+                    .into_iter()
+                    .map(|(zi, bianma, _weight)| (zi, bianma))
+                    .collect::<Vec<_>>();
+                write_selected_jianma(out, &entries);
+            }
+            OutputFormat::Rime => output::write_rime_dict(out, &entries, &options),
+            OutputFormat::Libime => output::write_libime_table(out, &entries, &rules, &code_keys),
+            OutputFormat::Ibus => output::write_ibus_table(out, &entries, &options, &rules, &code_keys),
+            OutputFormat::Json | OutputFormat::Jsonl => unreachable!(),
+        }
+    }
+
+    println!("最终简码得分：");
+    println!("韵码简\t\t{} 分", assignment.score);
+    if args.space_jianma {
+        println!("韵码加空格简\t{} 分", assignment.score + assignment.score_space);
+    }
+    if !inputs.families.is_empty() {
+        let rate = family::consistency(&assignment.selected, &inputs.families, &inputs.mabiao);
+        println!("部件家族一致率\t{:.2}%", rate * 100.0);
+    }
+}
+
+pub fn run_candidates(args: &CandidatesArgs, inputs: &Inputs) {
+    // 开启部件家族奖励或误触惩罚时，列出最后一轮指派所用的得分。
+    let candidates = if inputs.scoring.is_pairwise() {
+        assign_jianma(inputs, false).candidates
+    } else {
+        make_all_jianma_candidate(inputs)
+    };
+    let writer: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(File::create(path).expect("无法创建候选简码文件")),
+        None => Box::new(stdout()),
+    };
+
+    match args.format {
+        CandidatesFormat::Tsv => write_jianma_candidate(writer, &candidates),
+        CandidatesFormat::Json | CandidatesFormat::Jsonl => {
+            let candidates = sort_candidates(&candidates);
+            let records = output::candidate_records(&candidates, &inputs.mabiao, &inputs.corpora);
+            output::write_json_records(writer, &records, args.format == CandidatesFormat::Jsonl);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use argh::FromArgs;
+use compact_str::CompactString;
+
+use crate::decomposition::Decomposition;
+use crate::evaluate::{assignment_table, shortest_codes};
+use crate::{Inputs, assign_jianma, read_charset_file};
+
+/// 参与检查的字的范围。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// 拆分表中的所有字。
+    All,
+    /// 码表中的字。
+    Mabiao,
+    /// 参与简码计算的字。
+    Frequency,
+    /// 字集文件中的字。
+    File(PathBuf),
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "mabiao" => Ok(Self::Mabiao),
+            "frequency" => Ok(Self::Frequency),
+            "" => Err(String::from("字的范围为空")),
+            path => Ok(Self::File(PathBuf::from(path))),
+        }
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "isomorph")]
+/// 找出字根序列完全相同的结构重码字，按字频排列，并检查它们的全码是否相同、简码能否将其区分。
+/// 字根序列取自 --decomposition 的拆分表。
+pub struct IsomorphArgs {
+    #[argh(option, default = "Scope::Mabiao")]
+    /// 参与检查的字：all（拆分表中的所有字）、mabiao（码表中的字，默认）、frequency（参与简码计算的字），
+    /// 或者字集文件的路径。
+    charset: Scope,
+
+    #[argh(switch)]
+    /// 计算简码时允许空格简码。
+    space_jianma: bool,
+
+    #[argh(switch)]
+    /// 只列出全码相同的组。
+    collisions_only: bool,
+
+    #[argh(option, default = "50")]
+    /// 最多列出的组数，0 表示全部列出。默认为 50。
+    top: usize,
+}
+
+/// 一组结构重码字。
+struct Group<'a> {
+    roots: &'a [CompactString],
+    /// 组内各字及其字频，按字频降序排列。
+    members: Vec<(char, u64)>,
+    weight: u64,
+}
+
+/// 把范围内字根序列完全相同的字分为一组，只保留至少有两个字的组，按组内字频之和降序排列。
+fn isomorph_groups<'a>(
+    decomposition: &'a Decomposition,
+    in_scope: impl Fn(&char) -> bool,
+    weight_of: impl Fn(char) -> u64,
+) -> Vec<Group<'a>> {
+    let mut by_roots = HashMap::<&[CompactString], Vec<char>>::new();
+    for (zi, roots) in decomposition.iter().filter(|(zi, _)| in_scope(zi)) {
+        by_roots.entry(roots.as_slice()).or_default().push(*zi);
+    }
+
+    let mut groups = by_roots
+        .into_iter()
+        .filter(|(_, zis)| zis.len() > 1)
+        .map(|(roots, zis)| {
+            let mut members = zis.into_iter().map(|zi| (zi, weight_of(zi))).collect::<Vec<_>>();
+            members.sort_by(|a, b| a.1.cmp(&b.1).reverse().then(a.0.cmp(&b.0)));
+            let weight = members.iter().map(|(_, weight)| weight).sum();
+            Group { roots, members, weight }
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by(|a, b| a.weight.cmp(&b.weight).reverse().then(a.members.cmp(&b.members)));
+    groups
+}
+
+pub fn run<W: Write>(writer: W, args: &IsomorphArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+
+    if inputs.decomposition.is_empty() {
+        panic!("请用 --decomposition 指定拆分表");
+    }
+
+    let charset = match &args.charset {
+        Scope::File(path) => read_charset_file(path),
+        _ => HashSet::new(),
+    };
+    let in_scope = |zi: &char| match &args.charset {
+        Scope::All => true,
+        Scope::Mabiao => inputs.full_mabiao.contains_key(zi),
+        Scope::Frequency => inputs.mabiao.contains_key(zi),
+        Scope::File(_) => charset.contains(zi),
+    };
+
+    let weight_of = |zi: char| inputs.mabiao.get(&zi).map(|ch| ch.weight).unwrap_or(0);
+    let groups = isomorph_groups(&inputs.decomposition, in_scope, weight_of);
+
+    let assignment = assign_jianma(inputs, args.space_jianma);
+    let table = assignment_table(inputs, &assignment, args.space_jianma);
+    let shortest = shortest_codes(&table, &inputs.commit);
+
+    let full_code = |zi: char| inputs.full_mabiao.get(&zi).map(|ch| ch.bianma.as_str());
+
+    let mut listed = 0;
+    let mut collisions = 0;
+    let mut unresolved = 0;
+    for group in groups.iter() {
+        let mut by_code = BTreeMap::<&str, Vec<(char, u64)>>::new();
+        for &(zi, weight) in group.members.iter() {
+            if let Some(bianma) = full_code(zi) {
+                by_code.entry(bianma).or_default().push((zi, weight));
+            }
+        }
+        by_code.retain(|_, members| members.len() > 1);
+
+        collisions += by_code.len();
+        unresolved += by_code
+            .values()
+            .filter(|members| !shortest.contains_key(&members[0].0))
+            .count();
+
+        if args.collisions_only && by_code.is_empty() {
+            continue
+        }
+        if args.top > 0 && listed >= args.top {
+            continue
+        }
+        listed += 1;
+
+        let members = group.members
+            .iter()
+            .map(|(zi, weight)| match full_code(*zi) {
+                Some(bianma) => format!("{zi}（{bianma}，{weight}）"),
+                None => format!("{zi}（无全码，{weight}）"),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(writer, "{}\t字频 {}\t{members}", group.roots.join(" "), group.weight).unwrap();
+
+        for (bianma, members) in by_code.iter() {
+            let (first, _) = members[0];
+            let zis = members.iter().map(|(zi, _)| zi.to_string()).collect::<Vec<_>>().join("");
+            let status = match shortest.get(&first) {
+                Some(jianma) => format!("「{first}」有简码 {jianma}，已区分"),
+                None => format!("「{first}」没有简码，未区分"),
+            };
+            writeln!(writer, "  全码重码 {bianma}：{zis}，{status}").unwrap();
+        }
+    }
+
+    writeln!(writer).unwrap();
+    writeln!(
+        writer,
+        "共 {} 组结构重码字，其中 {collisions} 处全码相同，{unresolved} 处最常用的字没有简码",
+        groups.len(),
+    ).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scope() {
+        assert_eq!("all".parse::<Scope>(), Ok(Scope::All));
+        assert_eq!("mabiao".parse::<Scope>(), Ok(Scope::Mabiao));
+        assert_eq!("frequency".parse::<Scope>(), Ok(Scope::Frequency));
+        assert_eq!("tonggui.txt".parse::<Scope>(), Ok(Scope::File(PathBuf::from("tonggui.txt"))));
+        assert!("".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn group_identical_decompositions() {
+        let decomposition = [('只', "口 八"), ('叭', "口 八"), ('兄', "口 儿"), ('叹', "口 又"), ('叒', "又 又 又"), ('㕛', "又 又 又"), ('另', "口 力")]
+            .into_iter()
+            .map(|(zi, roots)| (zi, roots.split(' ').map(CompactString::from).collect()))
+            .collect::<Decomposition>();
+        let weights = HashMap::from([('只', 100), ('叭', 10), ('叒', 1)]);
+        let weight_of = |zi: char| weights.get(&zi).copied().unwrap_or(0);
+
+        let groups = isomorph_groups(&decomposition, |_| true, weight_of);
+        let summary = groups
+            .iter()
+            .map(|group| (group.roots.join(" "), group.members.clone(), group.weight))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            (String::from("口 八"), vec![('只', 100), ('叭', 10)], 110),
+            (String::from("又 又 又"), vec![('叒', 1), ('㕛', 0)], 1),
+        ]);
+
+        // 范围外的字不参与分组，只剩一个字的组不列出。
+        let groups = isomorph_groups(&decomposition, |zi| *zi != '叭', weight_of);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members, vec![('叒', 1), ('㕛', 0)]);
+    }
+}
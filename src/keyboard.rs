@@ -0,0 +1,330 @@
+//! 键盘布局：各键的位置与指法、哪些键用于编码（其中哪些是B区键）、哪些键用于选重。
+//! 用于估算击键的难易（当量）、判断相邻键，并决定编码的字母表。默认为 QWERTY 布局与标准指法。
+
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::read_file;
+
+/// 默认布局各行的键，从上到下依次为上排、中排（基准行）、下排。
+const ROWS: [&str; 3] = ["qwertyuiop", "asdfghjkl;", "zxcvbnm,./"];
+
+/// 一个键的用途。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// 编码键。
+    Code,
+    /// 编码键，且为B区键：以之结尾的编码直接上屏。
+    BArea,
+    /// 选重键，不用于编码。
+    Select,
+    /// 不用于编码，只用于判断相邻键与估算当量。
+    Other,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "code" => Ok(Self::Code),
+            "b" => Ok(Self::BArea),
+            "select" => Ok(Self::Select),
+            "none" => Ok(Self::Other),
+            _ => Err(format!("未知的键位用途：{s}")),
+        }
+    }
+}
+
+/// 一个键的位置、指法与用途。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub key: char,
+    /// 0 为上排，1 为基准行，2 为下排。
+    pub row: usize,
+    /// 在该排中的位置，从左数起。
+    pub column: usize,
+    /// 手指，0 至 3 为左手小指至食指，4 至 7 为右手食指至小指。
+    pub finger: usize,
+    pub role: Role,
+}
+
+impl Key {
+    pub fn is_left(&self) -> bool {
+        self.finger < 4
+    }
+
+    pub fn is_code(&self) -> bool {
+        matches!(self.role, Role::Code | Role::BArea)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    keys: Vec<Key>,
+}
+
+impl Layout {
+    /// QWERTY 布局与标准指法，a 至 z 为编码键，其中 aeiou 为B区键，标点键不用于编码。
+    pub fn qwerty() -> Self {
+        let keys = ROWS
+            .iter()
+            .enumerate()
+            .flat_map(|(row, keys)| {
+                keys.chars().enumerate().map(move |(column, key)| {
+                    // 食指各管两列：左手 4、5 列，右手 6、7 列。
+                    let finger = match column {
+                        0..=3 => column,
+                        4 => 3,
+                        5 => 4,
+                        _ => column - 2,
+                    };
+                    let role = match key {
+                        'a' | 'e' | 'i' | 'o' | 'u' => Role::BArea,
+                        'a'..='z' => Role::Code,
+                        _ => Role::Other,
+                    };
+                    Key { key, row, column, finger, role }
+                })
+            })
+            .collect();
+
+        Self { keys }
+    }
+
+    /// 读取键盘布局文件，一行一个键：键、排、列、手指与用途，用 \t 隔开。
+    /// 排与列从 0 数起；手指 0 至 3 为左手小指至食指，4 至 7 为右手食指至小指；
+    /// 用途为 code（编码键，可省略）、b（B区编码键）、select（选重键）或 none（不用于编码）。
+    /// 每一排都视作相对上一排向右错开半键，列号按各排从左数起填写即可，见 adjacent。
+    pub fn read(path: &Path) -> Self {
+        let mut keys = Vec::<Key>::new();
+
+        read_file(path, "键盘布局", |line_no, line| {
+            let fields = line.split('\t').map(|field| field.trim()).collect::<Vec<_>>();
+            if fields.len() < 4 {
+                panic!("键盘布局第{}行存在错误", line_no + 1);
+            }
+
+            let mut chars = fields[0].chars();
+            let (Some(key), None) = (chars.next(), chars.next()) else {
+                panic!("键盘布局第{}行的键无效", line_no + 1);
+            };
+            let key = key.to_ascii_lowercase();
+            let number = |field: &str| field
+                .parse::<usize>()
+                .unwrap_or_else(|_| panic!("键盘布局第{}行的数字无效：{field}", line_no + 1));
+            let finger = number(fields[3]);
+            if finger > 7 {
+                panic!("键盘布局第{}行的手指无效：{finger}", line_no + 1);
+            }
+            let role = match fields.get(4) {
+                Some(role) if !role.is_empty() => role
+                    .parse()
+                    .unwrap_or_else(|e| panic!("键盘布局第{}行：{e}", line_no + 1)),
+                _ => Role::Code,
+            };
+
+            if keys.iter().any(|k| k.key == key) {
+                panic!("键盘布局第{}行的键 {key} 重复", line_no + 1);
+            }
+            keys.push(Key { key, row: number(fields[1]), column: number(fields[2]), finger, role });
+        });
+
+        if !keys.iter().any(|key| key.is_code()) {
+            panic!("键盘布局 {} 中没有编码键", path.display());
+        }
+
+        Self { keys }
+    }
+
+    /// 以 b_area 中的键为B区键，其余编码键不再是B区键。
+    pub fn set_b_area(&mut self, b_area: &[char]) {
+        for c in b_area.iter() {
+            if !self.key(*c).is_some_and(|key| key.is_code()) {
+                panic!("B区键 {c} 不是键盘布局中的编码键");
+            }
+        }
+
+        for key in self.keys.iter_mut().filter(|key| key.is_code()) {
+            key.role = if b_area.contains(&key.key) { Role::BArea } else { Role::Code };
+        }
+    }
+
+    pub fn key(&self, c: char) -> Option<&Key> {
+        self.keys.iter().find(|key| key.key == c)
+    }
+
+    /// 布局中的所有键。
+    pub fn keys(&self) -> impl Iterator<Item = char> + '_ {
+        self.keys.iter().map(|key| key.key)
+    }
+
+    /// 编码的字母表。
+    pub fn code_keys(&self) -> Vec<char> {
+        self.keys.iter().filter(|key| key.is_code()).map(|key| key.key).collect()
+    }
+
+    pub fn b_area(&self) -> Vec<char> {
+        self.keys.iter().filter(|key| key.role == Role::BArea).map(|key| key.key).collect()
+    }
+
+    pub fn selection_keys(&self) -> Vec<char> {
+        self.keys.iter().filter(|key| key.role == Role::Select).map(|key| key.key).collect()
+    }
+
+    /// 编码是否只由编码键组成。
+    pub fn is_valid_bianma(&self, bianma: &str) -> bool {
+        !bianma.is_empty() && bianma.chars().all(|c| self.key(c).is_some_and(|key| key.is_code()))
+    }
+
+    /// 单键的难度：基准行最易，上排次之，其他排最难；小指额外加难。
+    pub fn key_cost(&self, c: char) -> f64 {
+        let Some(key) = self.key(c) else {
+            return 1.5;
+        };
+
+        let row = match key.row {
+            1 => 1.0,
+            0 => 1.2,
+            _ => 1.4,
+        };
+        let pinky = if key.finger == 0 || key.finger == 7 { 0.3 } else { 0.0 };
+        row + pinky
+    }
+
+    /// 连续两键的难度（当量）：左右手互击最易，同手异指次之，同指异键最难，跨两排再加难。
+    pub fn pair_cost(&self, a: char, b: char) -> f64 {
+        let (Some(ka), Some(kb)) = (self.key(a), self.key(b)) else {
+            return 1.5;
+        };
+
+        if a == b {
+            1.2
+        } else if ka.is_left() != kb.is_left() {
+            1.0
+        } else if ka.finger != kb.finger {
+            1.3
+        } else if ka.row.abs_diff(kb.row) > 1 {
+            2.5
+        } else {
+            2.0
+        }
+    }
+
+    /// 一个编码的平均当量。单码编码取该键的难度。
+    pub fn equivalence(&self, bianma: &str) -> f64 {
+        let chars = bianma.chars().collect::<Vec<_>>();
+        match chars.len() {
+            0 => 0.0,
+            1 => self.key_cost(chars[0]),
+            n => chars.windows(2).map(|pair| self.pair_cost(pair[0], pair[1])).sum::<f64>() / (n - 1) as f64,
+        }
+    }
+
+    /// 两键是否相邻：同排左右相邻，或上下排相邻（下排键相对上排键向右错开半键）。
+    pub fn adjacent(&self, a: char, b: char) -> bool {
+        let (Some(ka), Some(kb)) = (self.key(a), self.key(b)) else {
+            return false;
+        };
+
+        let (upper, lower) = if ka.row <= kb.row { (ka, kb) } else { (kb, ka) };
+        match lower.row - upper.row {
+            0 => upper.column.abs_diff(lower.column) == 1,
+            1 => lower.column == upper.column || lower.column + 1 == upper.column,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn qwerty_roles() {
+        let layout = Layout::qwerty();
+        assert_eq!(layout.code_keys().len(), 26);
+        assert_eq!(layout.b_area(), vec!['e', 'u', 'i', 'o', 'a']);
+        assert!(layout.selection_keys().is_empty());
+        assert!(layout.is_valid_bianma("abc"));
+        assert!(!layout.is_valid_bianma("a;"));
+        assert!(!layout.is_valid_bianma(""));
+    }
+
+    #[test]
+    fn qwerty_adjacency() {
+        let layout = Layout::qwerty();
+        for (a, b) in [('a', 'q'), ('a', 'w'), ('a', 's'), ('z', 'a'), ('z', 's'), ('z', 'x'), ('g', 't'), ('g', 'y'), ('b', 'g'), ('b', 'h')] {
+            assert!(layout.adjacent(a, b), "{a}{b}");
+            assert!(layout.adjacent(b, a), "{b}{a}");
+        }
+        for (a, b) in [('a', 'a'), ('a', 'e'), ('z', 'q'), ('z', 'w'), ('q', 'z'), ('g', 'r'), ('b', 'f'), ('p', 'a'), ('a', '1')] {
+            assert!(!layout.adjacent(a, b), "{a}{b}");
+        }
+    }
+
+    #[test]
+    fn qwerty_costs() {
+        let layout = Layout::qwerty();
+        assert_eq!(layout.key_cost('f'), 1.0);
+        assert_eq!(layout.key_cost('q'), 1.5);
+        assert_eq!(layout.pair_cost('f', 'j'), 1.0);
+        assert_eq!(layout.pair_cost('f', 'd'), 1.3);
+        assert_eq!(layout.pair_cost('r', 'v'), 2.5);
+        assert_eq!(layout.equivalence("fjf"), 1.0);
+    }
+
+    fn read_layout(name: &str, content: &str) -> Layout {
+        let path = std::env::temp_dir().join(format!("makejian-{}-{name}.txt", std::process::id()));
+        fs::write(&path, content).unwrap();
+        let layout = Layout::read(&path);
+        fs::remove_file(&path).unwrap();
+        layout
+    }
+
+    #[test]
+    fn read_layout_file() {
+        let layout = read_layout("ok", concat!(
+            "# 键\t排\t列\t指\t用途\n",
+            "A\t1\t0\t0\tb\n",
+            "s\t1\t1\t1\n",
+            ";\t1\t2\t2\tcode\n",
+            "'\t1\t3\t3\tselect\n",
+            "z\t2\t0\t0\tnone\n",
+        ));
+        assert_eq!(layout.code_keys(), vec!['a', 's', ';']);
+        assert_eq!(layout.b_area(), vec!['a']);
+        assert_eq!(layout.selection_keys(), vec!['\'']);
+        assert!(layout.adjacent('z', 'a'));
+        assert!(layout.adjacent('z', 's'));
+        assert!(layout.is_valid_bianma("a;s"));
+        assert!(!layout.is_valid_bianma("az"));
+    }
+
+    #[test]
+    #[should_panic(expected = "重复")]
+    fn read_layout_rejects_duplicate_keys() {
+        read_layout("dup", "a\t1\t0\t0\na\t1\t1\t1\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "手指无效")]
+    fn read_layout_rejects_bad_finger() {
+        read_layout("finger", "a\t1\t0\t8\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "没有编码键")]
+    fn read_layout_requires_code_keys() {
+        read_layout("nocode", "a\t1\t0\t0\tnone\n");
+    }
+
+    #[test]
+    fn set_b_area_replaces_b_keys() {
+        let mut layout = Layout::qwerty();
+        layout.set_b_area(&['a', 'z']);
+        assert_eq!(layout.b_area(), vec!['a', 'z']);
+    }
+}
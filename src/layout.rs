@@ -0,0 +1,476 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use argh::FromArgs;
+use compact_str::CompactString;
+
+use crate::rule::CodingRule;
+use crate::keyboard::Layout;
+use crate::{Inputs, read_file};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "optimize-layout")]
+/// 以局部搜索优化字根的大码键位，使按字频加权的全码选重率与击键当量尽量低。
+/// 需要用 --root-keys 指定初始的字根键位表，字根序列取自 --decomposition 的拆分表。
+pub struct OptimizeLayoutArgs {
+    #[argh(option, default = "20000")]
+    /// 搜索的步数。默认为 20000。
+    iterations: usize,
+
+    #[argh(option, default = "1")]
+    /// 随机数种子，相同的种子与输入得到相同的结果。默认为 1。
+    seed: u64,
+
+    #[argh(option, default = "0.0")]
+    /// 模拟退火的初始温度，为 0 时只接受不变差的移动。默认为 0。
+    temperature: f64,
+
+    #[argh(option, default = "0.01")]
+    /// 击键当量在目标函数中的权重，目标 = 选重率 + 权重 × 平均当量。默认为 0.01。
+    equivalence_weight: f64,
+
+    #[argh(option, default = "String::new()")]
+    /// 大码可用的键。默认为键盘布局中的所有编码键。
+    keys: String,
+
+    #[argh(option, default = "String::new()")]
+    /// 不可移动的字根，用逗号隔开。
+    fixed: String,
+
+    #[argh(option, default = "PathBuf::new()")]
+    /// 字根分组文件，一行一组字根（用空格隔开），同组的字根始终共用大码，一起移动。
+    groups: PathBuf,
+
+    #[argh(option, default = "1000")]
+    /// 每隔多少步输出一次指标。默认为 1000。
+    report_every: usize,
+
+    #[argh(option, default = "PathBuf::new()")]
+    /// 优化后的字根键位表输出路径。
+    out_keys: PathBuf,
+
+    #[argh(option, default = "PathBuf::new()")]
+    /// 优化后的码表输出路径，格式与宇浩拆分文件相同，可直接用作 --mabiao。
+    out_mabiao: PathBuf,
+}
+
+/// xorshift64* 随机数生成器。
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // 种子为 0 时 xorshift 会一直输出 0。
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// [0, 1) 之间的浮点数。
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// 参与优化的字。
+struct Zi {
+    zi: char,
+    roots: Vec<usize>,
+    weight: u64,
+}
+
+/// 优化过程中的状态：各字根的编码、各字的全码，以及按全码分组的字。
+pub struct LayoutState<'a> {
+    rule: &'a CodingRule,
+    layout: &'a Layout,
+    root_names: Vec<CompactString>,
+    root_codes: Vec<CompactString>,
+    zis: Vec<Zi>,
+    /// 用到各字根的字。
+    users: Vec<Vec<usize>>,
+    codes: Vec<CompactString>,
+    by_code: HashMap<CompactString, Vec<usize>>,
+    total_weight: f64,
+    /// 需要选重的字频之和。
+    selection: u64,
+    /// 按字频加权的当量之和。
+    equivalence: f64,
+}
+
+/// 一组编码下需要选重的字频：字频最高者以外各字的字频之和。
+fn group_selection(members: &[usize], zis: &[Zi]) -> u64 {
+    let sum = members.iter().map(|i| zis[*i].weight).sum::<u64>();
+    let max = members.iter().map(|i| zis[*i].weight).max().unwrap_or(0);
+    sum - max
+}
+
+/// 按取码规则由字根编码推出全码。字根数不在规则范围内、或字根编码不够长时为 None。
+fn encode(rule: &CodingRule, root_codes: &[CompactString], roots: &[usize]) -> Option<CompactString> {
+    let keys = roots.iter().map(|root| root_codes[*root].as_str()).collect::<Vec<_>>();
+    rule.apply(&keys)
+}
+
+impl<'a> LayoutState<'a> {
+    /// 由参与简码计算的字建立状态。拆分不全、有字根不在键位表中或无法按取码规则编码的字不参与优化。
+    pub fn new(inputs: &'a Inputs, rule: &'a CodingRule) -> Self {
+        let mut root_names = inputs.root_keys.keys().cloned().collect::<Vec<_>>();
+        root_names.sort();
+        let root_index = root_names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i))
+            .collect::<HashMap<_, _>>();
+        let root_codes = root_names.iter().map(|name| inputs.root_keys[name].clone()).collect::<Vec<_>>();
+
+        let mut zis = inputs.mabiao
+            .iter()
+            .filter_map(|(zi, character)| {
+                let roots = inputs.decomposition
+                    .get(zi)?
+                    .iter()
+                    .map(|root| root_index.get(root).copied())
+                    .collect::<Option<Vec<_>>>()?;
+                let code = encode(rule, &root_codes, &roots)?;
+                Some((Zi { zi: *zi, roots, weight: character.weight }, code))
+            })
+            .collect::<Vec<_>>();
+        zis.sort_by_key(|(zi, _)| zi.zi);
+        let (zis, codes): (Vec<_>, Vec<_>) = zis.into_iter().unzip();
+
+        let mut users = vec![Vec::new(); root_names.len()];
+        for (i, zi) in zis.iter().enumerate() {
+            for root in zi.roots.iter().collect::<HashSet<_>>() {
+                users[*root].push(i);
+            }
+        }
+
+        let total_weight = zis.iter().map(|zi| zi.weight).sum::<u64>().max(1) as f64;
+        let mut state = Self {
+            rule,
+            layout: &inputs.layout,
+            root_names,
+            root_codes,
+            codes,
+            zis,
+            users,
+            by_code: HashMap::new(),
+            total_weight,
+            selection: 0,
+            equivalence: 0.0,
+        };
+
+        for i in 0..state.zis.len() {
+            state.insert(i);
+        }
+        state
+    }
+
+    fn encode(&self, i: usize) -> CompactString {
+        encode(self.rule, &self.root_codes, &self.zis[i].roots).expect("改动大码不会改变字能否按取码规则编码")
+    }
+
+    fn insert(&mut self, i: usize) {
+        let members = self.by_code.entry(self.codes[i].clone()).or_default();
+        self.selection -= group_selection(members, &self.zis);
+        members.push(i);
+        self.selection += group_selection(members, &self.zis);
+        self.equivalence += self.zis[i].weight as f64 * self.layout.equivalence(&self.codes[i]);
+    }
+
+    fn remove(&mut self, i: usize) {
+        let members = self.by_code.get_mut(&self.codes[i]).unwrap();
+        self.selection -= group_selection(members, &self.zis);
+        members.retain(|j| *j != i);
+        self.selection += group_selection(members, &self.zis);
+        if members.is_empty() {
+            self.by_code.remove(&self.codes[i]);
+        }
+        self.equivalence -= self.zis[i].weight as f64 * self.layout.equivalence(&self.codes[i]);
+    }
+
+    pub fn root_index(&self, name: &str) -> Option<usize> {
+        self.root_names.binary_search_by(|root| root.as_str().cmp(name)).ok()
+    }
+
+    pub fn major_key(&self, root: usize) -> char {
+        self.root_codes[root].chars().next().unwrap()
+    }
+
+    /// 把一组字根的大码改为 key，并重新编码受影响的字。
+    pub fn set_major_key(&mut self, roots: &[usize], key: char) {
+        let mut affected = roots
+            .iter()
+            .flat_map(|root| self.users[*root].iter().copied())
+            .collect::<Vec<_>>();
+        affected.sort_unstable();
+        affected.dedup();
+
+        for i in affected.iter() {
+            self.remove(*i);
+        }
+        for root in roots.iter() {
+            let mut code = CompactString::new("");
+            code.push(key);
+            code.push_str(&self.root_codes[*root][1..]);
+            self.root_codes[*root] = code;
+        }
+        for i in affected.iter() {
+            self.codes[*i] = self.encode(*i);
+            self.insert(*i);
+        }
+    }
+
+    /// 按字频加权的全码选重率。
+    pub fn selection_rate(&self) -> f64 {
+        self.selection as f64 / self.total_weight
+    }
+
+    /// 按字频加权的平均当量。
+    pub fn mean_equivalence(&self) -> f64 {
+        self.equivalence / self.total_weight
+    }
+
+    pub fn collision_groups(&self) -> usize {
+        self.by_code.values().filter(|members| members.len() > 1).count()
+    }
+
+    pub fn objective(&self, equivalence_weight: f64) -> f64 {
+        self.selection_rate() + equivalence_weight * self.mean_equivalence()
+    }
+
+    fn write_keys(&self, path: &Path) {
+        let mut writer = BufWriter::new(File::create(path).expect("无法创建字根键位表文件"));
+        for (root, code) in self.root_names.iter().zip(self.root_codes.iter()) {
+            let mut code = code.clone();
+            code.as_mut_str()[..1].make_ascii_uppercase();
+            writeln!(writer, "{root}\t{code}").unwrap();
+        }
+    }
+}
+
+/// 读取字根分组文件，一行一组字根，用空格隔开。
+fn read_groups(path: &Path) -> Vec<Vec<CompactString>> {
+    let mut result = Vec::new();
+
+    read_file(path, "字根分组文件", |_, line| {
+        result.push(line.split_whitespace().map(CompactString::new).collect());
+    });
+
+    result
+}
+
+/// 按拆分表与键位表为码表中的所有字编码，输出与宇浩拆分文件相同格式的码表。
+fn write_mabiao(path: &Path, inputs: &Inputs, root_keys: &HashMap<CompactString, CompactString>, rule: &CodingRule) {
+    let mut writer = BufWriter::new(File::create(path).expect("无法创建码表文件"));
+    let zis = inputs.full_mabiao.keys().collect::<BTreeSet<_>>();
+
+    for zi in zis {
+        let Some(roots) = inputs.decomposition.get(zi) else {
+            continue
+        };
+        let Some(keys) = roots.iter().map(|root| root_keys.get(root).map(|k| k.as_str())).collect::<Option<Vec<_>>>() else {
+            continue
+        };
+        if let Some(bianma) = rule.apply(&keys) {
+            writeln!(writer, "{zi}\t[{zi},{},,,CJK,]", bianma.to_ascii_uppercase()).unwrap();
+        }
+    }
+}
+
+fn report<W: Write>(writer: &mut W, iteration: usize, state: &LayoutState, equivalence_weight: f64) {
+    writeln!(
+        writer,
+        "{iteration}\t{:.6}\t{:.4}%\t{}\t{:.4}",
+        state.objective(equivalence_weight),
+        state.selection_rate() * 100.0,
+        state.collision_groups(),
+        state.mean_equivalence(),
+    ).unwrap();
+    writer.flush().unwrap();
+}
+
+pub fn run<W: Write>(writer: W, args: &OptimizeLayoutArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+
+    if inputs.decomposition.is_empty() || inputs.root_keys.is_empty() {
+        panic!("请用 --decomposition 与 --root-keys 指定拆分表与初始的字根键位表");
+    }
+
+    let keys = if args.keys.is_empty() {
+        inputs.layout.code_keys()
+    } else {
+        args.keys.to_lowercase().chars().collect::<Vec<_>>()
+    };
+    if let Some(key) = keys.iter().find(|key| !inputs.layout.key(**key).is_some_and(|key| key.is_code())) {
+        panic!("大码可用的键 {key} 不是键盘布局中的编码键");
+    }
+
+    let mut state = LayoutState::new(inputs, &inputs.root_rule);
+
+    let fixed = args.fixed
+        .split(',')
+        .map(|root| root.trim())
+        .filter(|root| !root.is_empty())
+        .map(|root| state.root_index(root).unwrap_or_else(|| panic!("固定的字根 {root} 不在字根键位表中")))
+        .collect::<HashSet<_>>();
+
+    // 可移动的单位：分组中的字根一起移动，其余字根各自移动。
+    let mut grouped = HashSet::new();
+    let mut units = Vec::new();
+    for group in read_groups(&args.groups) {
+        let group = group
+            .iter()
+            .map(|root| state.root_index(root).unwrap_or_else(|| panic!("分组中的字根 {root} 不在字根键位表中")))
+            .collect::<Vec<_>>();
+        grouped.extend(group.iter().copied());
+
+        // 同组字根先统一为固定字根（没有时为第一个字根）的大码。
+        let anchor = group.iter().find(|root| fixed.contains(root)).unwrap_or(&group[0]);
+        let key = state.major_key(*anchor);
+        state.set_major_key(&group, key);
+
+        if !group.iter().any(|root| fixed.contains(root)) {
+            units.push(group);
+        }
+    }
+    units.extend(
+        (0..state.root_names.len())
+            .filter(|root| !grouped.contains(root) && !fixed.contains(root))
+            .filter(|root| !state.users[*root].is_empty())
+            .map(|root| vec![root]),
+    );
+
+    if units.is_empty() {
+        panic!("没有可移动的字根");
+    }
+
+    writeln!(writer, "# 参与优化的字：{}，可移动的字根组：{}", state.zis.len(), units.len()).unwrap();
+    writeln!(writer, "步数\t目标\t选重率\t重码组\t平均当量").unwrap();
+    report(&mut writer, 0, &state, args.equivalence_weight);
+
+    let mut rng = XorShift::new(args.seed);
+    let mut current = state.objective(args.equivalence_weight);
+    let mut best = current;
+    let mut best_codes = state.root_codes.clone();
+
+    for iteration in 1..=args.iterations {
+        let unit = &units[rng.below(units.len())];
+        let old_key = state.major_key(unit[0]);
+        let new_key = keys[rng.below(keys.len())];
+        if new_key == old_key {
+            continue
+        }
+
+        state.set_major_key(unit, new_key);
+        let candidate = state.objective(args.equivalence_weight);
+        let temperature = args.temperature * (1.0 - iteration as f64 / args.iterations as f64);
+
+        let accept = candidate <= current
+            || (temperature > 0.0 && rng.unit() < ((current - candidate) / temperature).exp());
+        if accept {
+            current = candidate;
+            if current < best {
+                best = current;
+                best_codes = state.root_codes.clone();
+            }
+        } else {
+            state.set_major_key(unit, old_key);
+        }
+
+        if args.report_every > 0 && iteration % args.report_every == 0 {
+            report(&mut writer, iteration, &state, args.equivalence_weight);
+        }
+    }
+
+    // 回到搜索中最好的布局。
+    for (root, code) in best_codes.iter().enumerate() {
+        let key = code.chars().next().unwrap();
+        if state.major_key(root) != key {
+            state.set_major_key(&[root], key);
+        }
+    }
+
+    writeln!(writer, "# 最终结果").unwrap();
+    report(&mut writer, args.iterations, &state, args.equivalence_weight);
+
+    let mut moved = (0..state.root_names.len())
+        .filter(|root| state.root_codes[*root] != inputs.root_keys[&state.root_names[*root]])
+        .map(|root| format!("{} {}→{}", state.root_names[root], inputs.root_keys[&state.root_names[root]], state.root_codes[root]))
+        .collect::<Vec<_>>();
+    moved.sort();
+    writeln!(writer, "# 移动了 {} 个字根：{}", moved.len(), moved.join("，")).unwrap();
+
+    if !args.out_keys.as_os_str().is_empty() {
+        state.write_keys(&args.out_keys);
+    }
+    if !args.out_mabiao.as_os_str().is_empty() {
+        let root_keys = state.root_names.iter().cloned().zip(state.root_codes.iter().cloned()).collect();
+        write_mabiao(&args.out_mabiao, inputs, &root_keys, &inputs.root_rule);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn xorshift_is_deterministic() {
+        let mut a = XorShift::new(0);
+        let mut b = XorShift::new(0);
+        let first = (0..5).map(|_| a.next_u64()).collect::<Vec<_>>();
+        assert_eq!(first, (0..5).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert!(first.iter().any(|n| *n != 0));
+        assert_ne!(XorShift::new(1).next_u64(), XorShift::new(2).next_u64());
+
+        let mut rng = XorShift::new(7);
+        for _ in 0..1000 {
+            assert!(rng.below(26) < 26);
+            let unit = rng.unit();
+            assert!((0.0..1.0).contains(&unit));
+        }
+    }
+
+    #[test]
+    fn selection_excludes_the_most_frequent() {
+        let zis = [(100, 'a'), (30, 'b'), (5, 'c')]
+            .into_iter()
+            .map(|(weight, zi)| Zi { zi, roots: Vec::new(), weight })
+            .collect::<Vec<_>>();
+        assert_eq!(group_selection(&[], &zis), 0);
+        assert_eq!(group_selection(&[1], &zis), 0);
+        assert_eq!(group_selection(&[0, 1, 2], &zis), 35);
+        assert_eq!(group_selection(&[2, 1], &zis), 5);
+    }
+
+    #[test]
+    fn encode_by_rule() {
+        let rule = crate::encoder::DEFAULT_ROOT_RULE.parse::<CodingRule>().unwrap();
+        let root_codes = ["ko", "qu", "m"].map(CompactString::from);
+        assert_eq!(encode(&rule, &root_codes, &[0]).as_deref(), Some("ko"));
+        assert_eq!(encode(&rule, &root_codes, &[0, 1, 0]).as_deref(), Some("kqko"));
+        // 末根只有大码，取不出小码。
+        assert_eq!(encode(&rule, &root_codes, &[0, 2]), None);
+    }
+
+    #[test]
+    fn read_group_file() {
+        let path = std::env::temp_dir().join(format!("makejian-{}-groups.txt", std::process::id()));
+        fs::write(&path, "# 同组字根\n口 囗\n\n 犬  尤 \n").unwrap();
+        let groups = read_groups(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(groups, vec![vec!["口", "囗"], vec!["犬", "尤"]]);
+    }
+}
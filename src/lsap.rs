@@ -12,6 +12,7 @@ pub enum LSAPError {
     Infeasible,
 }
 
+#[allow(clippy::needless_return)]
 pub fn get_assigned_cost(
     nr: usize,
     nc: usize,
@@ -36,6 +37,7 @@ pub fn get_assigned_cost(
 /// * `nc` - number of columns in the cost matrix
 /// * `cost` - cost matrix flattened into a vector such that item at row i, column j can be accessed via cost[i * nc + j]
 /// * `maximize` - if true, solve the maximization problem instead of the minimization problem
+#[allow(clippy::needless_return, clippy::needless_range_loop, clippy::needless_borrow)]
 pub fn solve(
     mut nr: usize,
     mut nc: usize,
@@ -163,6 +165,7 @@ pub fn solve(
     return Ok((a, b));
 }
 
+#[allow(clippy::too_many_arguments, clippy::ptr_arg, clippy::needless_range_loop, clippy::needless_return)]
 fn augmenting_path(
     nc: usize,
     cost: &Vec<f64>,
@@ -240,6 +243,7 @@ fn augmenting_path(
     return (sink, min_val); // they assign p_minVal, we return instead
 }
 
+#[allow(clippy::ptr_arg)]
 fn argsort_iter<T: Ord>(v: &Vec<T>) -> Vec<usize> {
     let mut index = (0..v.len()).collect::<Vec<_>>();
     index.sort_by_key(|&i| &v[i]);
@@ -1,25 +1,71 @@
+mod collision;
+mod commit;
+mod decomposition;
+mod diff;
+mod encoder;
+mod evaluate;
+mod explain;
+mod family;
+mod freq;
+mod frequency;
+mod generate;
+mod isomorph;
+mod keyboard;
+mod layout;
 mod lsap;
+mod output;
+mod profile;
+mod reserve;
+mod rule;
+mod suggest;
+mod typo;
+mod validate;
+mod word;
 
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufWriter, Read, Write, stdout};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use argh::FromArgs;
-use compact_str::CompactString;
+use compact_str::{CompactString, ToCompactString};
+use serde::Serialize;
 
 #[derive(FromArgs)]
 /// 简码计算
 struct Args {
     #[argh(option, default=r#"String::from("beiyu:0.5,zhihu:0.5,tw:0.01")"#)]
-    /// 字频表，可以混合使用多个字频表。默认为 beiyu:0.5,zhihu:0.5,tw:0.01。
+    /// 字频表，可以混合使用多个字频表，写法为 名称或路径:权重。默认为 beiyu:0.5,zhihu:0.5,tw:0.01。
+    /// 名称指 frequency/ 下的 JSON 文件；路径可指向 JSON、TSV 或 CSV 文件，以 .gz 结尾时自动解压。
+    /// 名称或路径之后可用 @ 指定 OpenCC 格式的转换表，如 tw@opencc/TSCharacters.txt:0.01。
     frequency: String,
 
+    #[argh(option, default="frequency::Normalization::None")]
+    /// 混合前对每张字频表的归一化方式：none（原样使用次数，默认）、per-million（按总次数归一，即按每百万字的次数比较）、
+    /// rank（按名次）、log（取对数）或 zipf（按名次拟合 Zipf 分布）。
+    normalize: frequency::Normalization,
+
     #[argh(option, default=r#"PathBuf::from("mabiao/yuming_chaifen.count.txt")"#)]
-    /// 字根数量表，记录了一个字有多少个字根。
+    /// 字根数量表，记录了一个字有多少个字根。只在 --decomposition 写作 "" 时读取。
     count: PathBuf,
 
+    #[argh(option, default=r#"PathBuf::from("scripts/yuchai.txt")"#)]
+    /// 拆分表，一行一个字与它的字根（用 \t 隔开，字根之间用空格隔开）。默认为 scripts/yuchai.txt。
+    /// 字根数量直接由拆分表得出；写作 "" 时不读取拆分表，改由 --count 读取字根数量。
+    decomposition: PathBuf,
+
+    #[argh(option, default="PathBuf::new()")]
+    /// 字根键位表，一行一个字根与它的编码（用 \t 隔开，第一码为大码，第二码为小码）。
+    /// 指定后，全码按 --decomposition 与 --root-rule 推出，不再取自码表；码表只用于限定字的范围。
+    root_keys: PathBuf,
+
+    #[argh(option, default=r#"encoder::DEFAULT_ROOT_RULE.parse().unwrap()"#)]
+    /// 单字的取码规则，写法与 --word-rule 相同，元素为字根，大写取大码，小写取小码。
+    /// 默认为 1:Aa,2:AaBb,3:ABCc,4+:ABCz。
+    root_rule: rule::CodingRule,
+
     #[argh(option, default=r#"PathBuf::from("mabiao/yuming_chaifen.allow.txt")"#)]
     /// 可用编码表，里头列出被额外允许的一些编码。
     /// 一行一个编码，# 开头的行会被忽略。
@@ -35,29 +81,374 @@ struct Args {
     /// 一行一个编码与汉字（用 \t 隔开），# 开头的行会被忽略。
     additional: PathBuf,
 
-    #[argh(option, default=r#"PathBuf::from("output/yuming.txt")"#)]
-    /// 简码表输出路径。
-    out: PathBuf,
+    #[argh(option, default=r#"String::from("b-area").parse().unwrap()"#)]
+    /// 上屏规则，多条用逗号隔开：b-area（以B区键结尾的编码不必按空格，由下一字的首码顶屏，默认）、
+    /// b-area-auto（以B区键结尾的编码打完即上屏）、max-length:N（码长达到 N 即上屏）或 space（都按空格上屏）。
+    /// 打完即上屏的编码会挡住以它开头的更长编码，这样的简码不参与计算。
+    commit: commit::CommitRules,
+
+    #[argh(option, default="PathBuf::new()")]
+    /// 保留编码表，一行一个模式：类型（prefix、glob 或 regex）与模式，用空白隔开，如 prefix z。
+    /// 匹配的编码不参与任何阶段的简码计算，# 开头的行会被忽略。
+    reserved: PathBuf,
+
+    #[argh(option, default="PathBuf::new()")]
+    /// 键盘布局文件，一行一个键：键、排、列、手指与用途（code、b、select 或 none），用 \t 隔开。
+    /// 决定编码的字母表、B区键、选重键，以及当量与相邻键的计算。默认为 QWERTY 布局，a 至 z 为编码键。
+    layout: PathBuf,
+
+    #[argh(option, default="String::new()")]
+    /// B区键位，指定后取代键盘布局中的B区键；默认取键盘布局中的B区键，默认布局为 aeiou。
+    /// 代码永远会假设空格是B区键位之一，因此不需要加入空格。
+    b_area: String,
 
-    #[argh(switch)]
-    /// 打印候选简码。
-    print_candidates: bool,
 
-    #[argh(switch)]
-    /// 允许空格简码。
-    space_jianma: bool,
+    #[argh(option, default="PathBuf::new()")]
+    /// 词频表，一行一个词组与词频（用 \t 隔开），# 开头的行会被忽略。
+    /// 指定后，词组与单字一同参与简码计算。
+    words: PathBuf,
 
-    #[argh(option, default=r#"String::from("aeiou")"#)]
-    /// B区键位，默认为 aeiou。代码永远会假设空格是B区键位之一，因此不需要加入空格。
-    b_area: String,
+    #[argh(option, default=r#"rule::DEFAULT_WORD_RULE.parse().unwrap()"#)]
+    /// 构词规则，由词中各字的全码推出词组的全码，写法如 2:AaBb,3:ABCc,4+:ABCZ（默认）。
+    word_rule: rule::CodingRule,
+
+    #[argh(option, default="1.0")]
+    /// 词频的混合权重，词频乘上此值后与字频比较。默认为 1.0。
+    word_weight: f64,
+
+    #[argh(option, default="MissingPolicy::Drop")]
+    /// 不在字频表中的字如何处理：drop（不参与简码计算，默认）、floor（以 --missing-floor 为字频）
+    /// 或 prior（按 --missing-prior 中所属字集的先验字频，不属于任何字集时以 --missing-floor 为字频）。
+    missing: MissingPolicy,
+
+    #[argh(option, default="10")]
+    /// 不在字频表中的字的最低字频。默认为 10。
+    missing_floor: u64,
+
+    #[argh(option, default="String::new()")]
+    /// 字集及其先验字频，写法为 路径:字频，多个字集用逗号隔开，如 tonggui1.txt:1000,tonggui2.txt:100。
+    /// 字集文件中的每个字都属于该字集，# 开头的行会被忽略。一个字属于多个字集时取最高的先验字频。
+    missing_prior: String,
 
     #[argh(switch)]
-    /// 按频率排序生成出来的简码表。
-    sort_freq: bool,
+    /// 不参与简码计算的字不再占用其全码，使这些全码也能用作简码。
+    unblock_dropped: bool,
 
     #[argh(positional, default=r#"PathBuf::from("mabiao/yuming_chaifen.dict.yaml")"#)]
-    /// 宇浩拆分文件
+    /// 宇浩拆分文件，写在子命令之前。默认为 mabiao/yuming_chaifen.dict.yaml。
     mabiao: PathBuf,
+
+    #[argh(subcommand)]
+    command: Command,
+}
+
+/// 不在字频表中的字的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MissingPolicy {
+    Drop,
+    Floor,
+    Prior,
+}
+
+impl FromStr for MissingPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Self::Drop),
+            "floor" => Ok(Self::Floor),
+            "prior" => Ok(Self::Prior),
+            _ => Err(format!("未知的处理方式：{s}")),
+        }
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Generate(generate::GenerateArgs),
+    Candidates(generate::CandidatesArgs),
+    Evaluate(evaluate::EvaluateArgs),
+    Diff(diff::DiffArgs),
+    Validate(validate::ValidateArgs),
+    Explain(explain::ExplainArgs),
+    SuggestAllow(suggest::SuggestAllowArgs),
+    SuggestPredefined(suggest::SuggestPredefinedArgs),
+    Freq(freq::FreqArgs),
+    Profiles(profile::ProfilesArgs),
+    Isomorph(isomorph::IsomorphArgs),
+    Collisions(collision::CollisionsArgs),
+    OptimizeLayout(layout::OptimizeLayoutArgs),
+    Typos(typo::TyposArgs),
+    Reserved(reserve::ReservedArgs),
+    Shadows(commit::ShadowsArgs),
+}
+
+/// 各子命令共用的输入数据。
+#[derive(Clone)]
+struct Inputs {
+    /// 码表中的所有字，不论是否出现在字频表中。
+    full_mabiao: HashMap<char, Character>,
+    /// 参与简码计算的字，已初始化字频与字根数量。
+    mabiao: HashMap<char, Character>,
+    /// 全码不能用作简码的字及其全码。除 mabiao 中的字以外，默认也包括不参与简码计算的字。
+    blockers: HashMap<char, CompactString>,
+    /// 各字频表的名称。
+    corpora: Vec<CompactString>,
+    alloweds: Vec<CompactString>,
+    predefineds: Vec<Predefined>,
+    additionals: Vec<Predefined>,
+    /// 可用、预定义与额外定义编码表中格式有误、未读入的行。
+    malformed: Vec<String>,
+    /// 保留编码的模式，未指定时为空。
+    reserved: reserve::Reserved,
+    /// B区键，取自键盘布局。
+    b_area: Vec<char>,
+    layout: keyboard::Layout,
+    /// 上屏规则，已指定B区键。
+    commit: commit::CommitRules,
+    /// 词频表中能按构词规则推出全码的词组，按词频降序排列。
+    words: Vec<word::Word>,
+    word_rule: rule::CodingRule,
+    /// 拆分表中各字的字根序列，未指定拆分表时为空。
+    decomposition: decomposition::Decomposition,
+    /// 字根键位表，未指定时为空。
+    root_keys: HashMap<CompactString, CompactString>,
+    root_rule: rule::CodingRule,
+    /// 部件家族，未开启家族奖励时为空。
+    families: family::Families,
+    scoring: Scoring,
+}
+
+/// 计算简码时的部件家族奖励与误触惩罚，由 generate、candidates 与 evaluate 的参数指定，其他子命令都不开启。
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scoring {
+    family_bonus: f64,
+    typo_penalty: f64,
+    /// 重新指派的最多轮数。
+    pairwise_rounds: usize,
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self { family_bonus: 0.0, typo_penalty: 0.0, pairwise_rounds: 3 }
+    }
+}
+
+impl Scoring {
+    /// 是否需要反复重新指派。
+    fn is_pairwise(&self) -> bool {
+        self.family_bonus > 0.0 || self.typo_penalty > 0.0
+    }
+}
+
+/// 为子命令的参数结构体加上部件家族奖励与误触惩罚的选项，以及由这些选项构造 Scoring 的 scoring()。
+/// argh 不支持嵌套的参数结构体，generate、candidates 与 evaluate 借此共用同一组选项。
+macro_rules! with_scoring_args {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident { $($fields:tt)* }) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($fields)*
+
+            #[argh(option, default="0.0")]
+            /// 部件家族一致性奖励，家族按 --decomposition 的拆分表划分。除首个字根外字根序列相同的字为同一家族，
+            /// 候选简码与同家族其他字的简码模式（阶段与码长）相同时，得分乘上 1 + 奖励 × 相同模式的字频占比。
+            /// 默认为 0，即不奖励。
+            family_bonus: f64,
+
+            #[argh(option, default="0.0")]
+            /// 误触惩罚。候选简码打错一次（相邻键替换或相邻两码颠倒）便会上屏其他字时，
+            /// 得分除以 1 + 惩罚 × Σ 两字字频的几何平均 / 本字字频。默认为 0，即不惩罚。
+            typo_penalty: f64,
+
+            #[argh(option, default="3")]
+            /// 计算部件家族奖励与误触惩罚时最多重新指派的轮数。默认为 3。
+            pairwise_rounds: usize,
+        }
+
+        impl $name {
+            pub fn scoring(&self) -> $crate::Scoring {
+                $crate::Scoring {
+                    family_bonus: self.family_bonus,
+                    typo_penalty: self.typo_penalty,
+                    pairwise_rounds: self.pairwise_rounds,
+                }
+            }
+        }
+    };
+}
+pub(crate) use with_scoring_args;
+
+/// 读取码表。路径为空时返回空码表，此时字的范围由拆分表决定。
+fn read_mabiao(path: &Path) -> HashMap<char, Character> {
+    if path.as_os_str().is_empty() {
+        return HashMap::new();
+    }
+
+    let mut mabiao = String::new();
+    File::open(path)
+        .expect("无法打开码表")
+        .read_to_string(&mut mabiao)
+        .expect("无法读取码表");
+
+    get_viable_mabiao(&mabiao)
+}
+
+/// 按 frequency 混合字频，返回参与简码计算的字、各字频表的名称，以及占用全码的字。
+fn weigh_mabiao(
+    args: &Args,
+    full_mabiao: &HashMap<char, Character>,
+    frequency: &str,
+) -> (HashMap<char, Character>, Vec<CompactString>, HashMap<char, CompactString>) {
+    let mut mabiao = full_mabiao.clone();
+    let corpora = initialize_weight(&mut mabiao, frequency, args.normalize);
+    apply_missing_policy(&mut mabiao, args.missing, args.missing_floor, &args.missing_prior);
+    let blockers = if args.unblock_dropped { &mabiao } else { full_mabiao }
+        .iter()
+        .map(|(zi, ch)| (*zi, ch.bianma.clone()))
+        .collect();
+
+    (mabiao, corpora, blockers)
+}
+
+/// 换用另一组字频表，其余输入保持不变。
+fn load_profile(args: &Args, inputs: &Inputs, frequency: &str) -> Inputs {
+    let (mabiao, corpora, blockers) = weigh_mabiao(args, &inputs.full_mabiao, frequency);
+
+    Inputs {
+        mabiao,
+        corpora,
+        blockers,
+        ..inputs.clone()
+    }
+}
+
+/// 读取键盘布局，并按 --b-area 改写其中的B区键。
+fn load_layout(args: &Args) -> keyboard::Layout {
+    let mut layout = if args.layout.as_os_str().is_empty() {
+        keyboard::Layout::qwerty()
+    } else {
+        keyboard::Layout::read(&args.layout)
+    };
+    if !args.b_area.is_empty() {
+        layout.set_b_area(&args.b_area.to_lowercase().chars().collect::<Vec<_>>());
+    }
+
+    layout
+}
+
+/// 全码含有编码键以外的字符时，在标准错误中提示。
+fn check_full_codes(full_mabiao: &HashMap<char, Character>, layout: &keyboard::Layout) {
+    let mut invalid = full_mabiao
+        .iter()
+        .filter(|(_, ch)| !layout.is_valid_bianma(&ch.bianma))
+        .map(|(zi, ch)| format!("{zi} {}", ch.bianma))
+        .collect::<Vec<_>>();
+    if invalid.is_empty() {
+        return;
+    }
+
+    invalid.sort();
+    invalid.truncate(20);
+    eprintln!("警告：码表中有全码含有键盘布局中编码键以外的字符，如：{}", invalid.join("，"));
+}
+
+/// 读取码表中各字的全码。指定了字根键位表时，全码按拆分表与取码规则推出。
+fn load_full_mabiao(
+    args: &Args,
+    decomposition: &decomposition::Decomposition,
+    root_keys: &HashMap<CompactString, CompactString>,
+) -> HashMap<char, Character> {
+    let mut full_mabiao = read_mabiao(&args.mabiao);
+    if !root_keys.is_empty() {
+        if decomposition.is_empty() {
+            panic!("使用 --root-keys 时须用 --decomposition 指定拆分表");
+        }
+        encoder::encode_mabiao(&mut full_mabiao, decomposition, root_keys, &args.root_rule);
+    }
+
+    full_mabiao
+}
+
+/// diff 只比较已有的简码表，只需各字的全码、字频与上屏规则，不读取其他输入。
+fn load_diff_inputs(args: &Args) -> (HashMap<char, Character>, commit::CommitRules) {
+    let layout = load_layout(args);
+    let root_keys = encoder::read_root_keys(&args.root_keys);
+    let decomposition = if root_keys.is_empty() {
+        decomposition::Decomposition::default()
+    } else {
+        decomposition::read_decomposition(&args.decomposition)
+    };
+    let full_mabiao = load_full_mabiao(args, &decomposition, &root_keys);
+    let (mabiao, _, _) = weigh_mabiao(args, &full_mabiao, &args.frequency);
+
+    (mabiao, args.commit.clone().with_b_area(&layout.b_area()))
+}
+
+/// 读取各子命令共用的输入数据。可用、预定义与额外定义编码表中有格式错误的行时直接报错退出。
+fn load_inputs(args: &Args) -> Inputs {
+    let inputs = read_inputs(args);
+    if let Some(error) = inputs.malformed.first() {
+        panic!("{error}");
+    }
+
+    inputs
+}
+
+/// 读取各子命令共用的输入数据，格式有误的行记入 malformed 而不报错，供 validate 检查。
+fn read_inputs(args: &Args) -> Inputs {
+    let layout = load_layout(args);
+    let decomposition = decomposition::read_decomposition(&args.decomposition);
+    let root_keys = encoder::read_root_keys(&args.root_keys);
+    let mut full_mabiao = load_full_mabiao(args, &decomposition, &root_keys);
+    if decomposition.is_empty() {
+        initialize_zigen_count(&mut full_mabiao, &args.count);
+    } else {
+        decomposition::apply_zigen_count(&mut full_mabiao, &decomposition);
+    }
+    check_full_codes(&full_mabiao, &layout);
+    let (mabiao, corpora, blockers) = weigh_mabiao(args, &full_mabiao, &args.frequency);
+    let words = word::load_words(&args.words, &full_mabiao, &args.word_rule, args.word_weight);
+
+    let (alloweds, allow_errors) = read_allow_file(&args.allow);
+    let (predefineds, predefined_errors) = read_predefined_file(&args.predefined);
+    let (additionals, additional_errors) = read_additional_file(&args.additional);
+
+    Inputs {
+        full_mabiao,
+        mabiao,
+        blockers,
+        corpora,
+        alloweds,
+        predefineds,
+        additionals,
+        malformed: [allow_errors, predefined_errors, additional_errors].concat(),
+        reserved: reserve::read_reserved(&args.reserved),
+        b_area: layout.b_area(),
+        commit: args.commit.clone().with_b_area(&layout.b_area()),
+        layout,
+        words,
+        word_rule: args.word_rule.clone(),
+        decomposition,
+        root_keys,
+        root_rule: args.root_rule.clone(),
+        families: family::Families::default(),
+        scoring: Scoring::default(),
+    }
+}
+
+/// 读取输入，并按子命令的参数开启部件家族奖励与误触惩罚。
+fn load_scored_inputs(args: &Args, scoring: Scoring) -> Inputs {
+    let mut inputs = load_inputs(args);
+    if scoring.family_bonus > 0.0 {
+        if inputs.decomposition.is_empty() {
+            panic!("使用 --family-bonus 时须用 --decomposition 指定拆分表");
+        }
+        inputs.families = family::Families::new(&inputs.decomposition, &inputs.mabiao);
+    }
+    inputs.scoring = scoring;
+
+    inputs
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -65,6 +456,50 @@ struct Character {
     bianma: CompactString,
     weight: u64,
     zigen_count: u64,
+    /// 各字频表对 weight 的贡献，次序与 --frequency 中的字频表一致。
+    frequencies: Vec<u64>,
+}
+
+/// 简码所属的阶段：韵码简（以B区键结尾）或空格简。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Stage {
+    Suffix,
+    Space,
+}
+
+/// 候选简码得分的各项系数，得分 = 字频 × length × zigen × space × family × typo。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Factors {
+    /// 简码比全码少打的码数带来的系数。
+    length: f64,
+    /// 字根数量带来的系数。
+    zigen: f64,
+    /// 空格简的码长惩罚，韵码简恒为 1。
+    space: f64,
+    /// 部件家族一致性奖励，未开启时恒为 1。
+    family: f64,
+    /// 误触惩罚，未开启时恒为 1。
+    typo: f64,
+}
+
+impl Factors {
+    /// 字频为 frequency 时的得分。
+    fn score(&self, frequency: u64) -> f64 {
+        frequency as f64 * self.length * self.zigen * self.space * self.family * self.typo
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    /// 汉字或词组。
+    zi: CompactString,
+    bianma: CompactString,
+    /// 计算得分所用的字频或词频。
+    frequency: u64,
+    stage: Stage,
+    factors: Factors,
+    score: u64,
 }
 
 fn get_viable_mabiao(content: &str) -> HashMap<char, Character> {
@@ -92,6 +527,7 @@ fn get_viable_mabiao(content: &str) -> HashMap<char, Character> {
                 bianma,
                 weight: 0,
                 zigen_count: 0,
+                frequencies: Vec::new(),
             };
             result.insert(zi.chars().next().unwrap(), character);
         }
@@ -100,32 +536,108 @@ fn get_viable_mabiao(content: &str) -> HashMap<char, Character> {
     result
 }
 
-fn initialize_weight(mabiao: &mut HashMap<char, Character>, frequency: &str) {
-    let freq_files = frequency.split(',')
-        .map(|file| {
-            let mut file = file.split(':');
-            let name = file.next().unwrap();
-            let weight = file.next().and_then(|w| w.parse::<f64>().ok()).unwrap_or(1.0);
-            (name, weight)
+/// 读取并混合字频表，返回各字频表的名称。
+fn initialize_weight(
+    mabiao: &mut HashMap<char, Character>,
+    frequency: &str,
+    normalization: frequency::Normalization,
+) -> Vec<CompactString> {
+    let sources = frequency::parse_sources(frequency);
+
+    for character in mabiao.values_mut() {
+        character.frequencies = vec![0; sources.len()];
+    }
+
+    let mut tables = sources
+        .iter()
+        .map(|source| {
+            let mut table = frequency::read_frequency_table(source);
+            frequency::normalize(&mut table, normalization);
+            table
         })
         .collect::<Vec<_>>();
-    
-    for (freq_file, weight) in freq_files {
-        let file = File::open(&format!("frequency/{freq_file}.json"))
-            .expect(&format!("无法打开字频表 {freq_file}"));
 
-        let json: HashMap<char, u64> = serde_json::from_reader(file)
-            .expect(&format!("无法解析字频表JSON {freq_file}"));
+    // 一对多转换时，按转换前各表的合计字频分配次数。
+    let mut reference = HashMap::<char, f64>::new();
+    for table in tables.iter() {
+        for (zi, freq) in table.iter() {
+            *reference.entry(*zi).or_default() += freq;
+        }
+    }
 
-        for (zi, freq) in json.iter() {
-            let zi_freq = ((*freq as f64) * weight).round() as u64;
+    let mut conversions = HashMap::new();
+    for (source, table) in sources.iter().zip(tables.iter_mut()) {
+        for path in source.conversions.iter() {
+            let conversion = conversions
+                .entry(path.clone())
+                .or_insert_with(|| frequency::read_conversion(path));
+            *table = conversion.convert(table, &reference);
+        }
+    }
+
+    for (i, (source, table)) in sources.iter().zip(tables.iter()).enumerate() {
+        for (zi, freq) in table.iter() {
+            let zi_freq = (freq * source.weight).round() as u64;
 
             if let Some(freq) = mabiao.get_mut(zi) {
                 freq.weight += zi_freq;
+                freq.frequencies[i] += zi_freq;
             }
         }
     }
 
+    sources.into_iter().map(|source| source.name).collect()
+}
+
+/// 读取字集文件，文件中的每个字都属于该字集。
+fn read_charset_file(path: &Path) -> HashSet<char> {
+    let mut result = HashSet::new();
+
+    read_file(path, "字集文件", |_, line| {
+        result.extend(line.chars().filter(|c| !c.is_whitespace()));
+    });
+
+    result
+}
+
+/// 按处理方式决定不在字频表中（混合后字频不超过 1）的字是否参与简码计算，以及参与时的字频。
+fn apply_missing_policy(
+    mabiao: &mut HashMap<char, Character>,
+    policy: MissingPolicy,
+    floor: u64,
+    prior: &str,
+) {
+    let charsets = prior
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| {
+            let (path, freq) = item
+                .rsplit_once(':')
+                .unwrap_or_else(|| panic!("字集 {item} 缺少先验字频"));
+            let freq = freq
+                .parse::<u64>()
+                .unwrap_or_else(|_| panic!("字集 {item} 的先验字频无效"));
+            (read_charset_file(Path::new(path.trim())), freq)
+        })
+        .collect::<Vec<_>>();
+
+    for (zi, character) in mabiao.iter_mut() {
+        if character.weight > 1 {
+            continue
+        }
+
+        character.weight = match policy {
+            MissingPolicy::Drop => 0,
+            MissingPolicy::Floor => floor,
+            MissingPolicy::Prior => charsets
+                .iter()
+                .filter(|(charset, _)| charset.contains(zi))
+                .map(|(_, freq)| *freq)
+                .max()
+                .unwrap_or(floor),
+        };
+    }
+
     mabiao.retain(|_zi, character| {
         character.weight > 1
     });
@@ -178,9 +690,9 @@ fn read_file(path: &Path, name: &'static str, mut callback: impl FnMut(usize, &s
     }
 
     File::open(path)
-        .expect(&format!("无法打开{name}"))
+        .unwrap_or_else(|_| panic!("无法打开{name}"))
         .read_to_string(&mut content)
-        .expect(&format!("无法读取{name}"));
+        .unwrap_or_else(|_| panic!("无法读取{name}"));
 
     for (i, line) in content.lines().enumerate() {
         let line = line
@@ -197,57 +709,88 @@ fn read_file(path: &Path, name: &'static str, mut callback: impl FnMut(usize, &s
     }
 }
 
-fn read_allow_file(path: &Path) -> Vec<CompactString> {
+/// 读取可用编码表。格式有误的行不读入，其行号与内容作为错误返回。
+fn read_allow_file(path: &Path) -> (Vec<CompactString>, Vec<String>) {
     let mut result = Vec::new();
+    let mut errors = Vec::new();
 
-    read_file(path, "可用编码表", |_, line| {
-        result.push(CompactString::from_str_to_lowercase(line))
+    read_file(path, "可用编码表", |line_no, line| {
+        if line.contains(char::is_whitespace) {
+            errors.push(format!("可用编码表第{}行应只有一个编码：{line}", line_no + 1));
+        } else {
+            result.push(CompactString::from_str_to_lowercase(line));
+        }
     });
 
-    result
+    (result, errors)
 }
 
-fn read_predefined_file(path: &Path) -> Vec<Predefined> {
+/// 解析预定义或额外定义编码表中的一行：汉字与编码，用 \t 隔开。
+fn parse_predefined_line(name: &str, line_no: usize, line: &str) -> Result<Predefined, String> {
+    match line.split_once('\t') {
+        Some((prefix, suffix)) if !prefix.is_empty() && !suffix.trim_ascii().is_empty() => Ok(Predefined {
+            bianma: CompactString::from_str_to_lowercase(suffix.trim_ascii()),
+            zi: CompactString::new(prefix),
+        }),
+        _ => Err(format!("{name}第{}行存在错误，应为用 \\t 隔开的字与编码：{line}", line_no + 1)),
+    }
+}
+
+/// 读取预定义编码表。格式有误的行不读入，其行号与内容作为错误返回。
+fn read_predefined_file(path: &Path) -> (Vec<Predefined>, Vec<String>) {
     let mut result = Vec::new();
+    let mut errors = Vec::new();
 
     read_file(path, "预定义编码表", |line_no, line| {
-        if let Some((prefix, suffix)) = line.split_once('\t') {
-            let bianma = CompactString::from_str_to_lowercase(suffix);
-            let zi = CompactString::new(prefix);
-            result.push(Predefined { bianma, zi });
-        } else {
-            panic!("预定义编码表第{}行存在错误", line_no + 1);
+        match parse_predefined_line("预定义编码表", line_no, line) {
+            Ok(pre) => result.push(pre),
+            Err(error) => errors.push(error),
         }
     });
 
-    result
+    (result, errors)
 }
 
-fn read_additional_file(path: &Path) -> Vec<Predefined> {
+/// 读取额外定义编码表。格式有误的行不读入，其行号与内容作为错误返回。
+fn read_additional_file(path: &Path) -> (Vec<Predefined>, Vec<String>) {
     let mut result = Vec::new();
+    let mut errors = Vec::new();
 
     read_file(path, "额外定义编码表", |line_no, line| {
-        if let Some((prefix, suffix)) = line.split_once('\t') {
-            let bianma = CompactString::from_str_to_lowercase(suffix);
-            let zi = CompactString::new(prefix);
-            result.push(Predefined { bianma, zi });
-        } else {
-            panic!("额外定义编码表第{}行存在错误", line_no + 1);
+        match parse_predefined_line("额外定义编码表", line_no, line) {
+            Ok(pre) => result.push(pre),
+            Err(error) => errors.push(error),
         }
     });
 
-    result
+    (result, errors)
+}
+
+/// 全码可能的韵码简：取全码的前若干码，再加上全码的末码。
+fn suffix_jianma_codes(bianma: &str) -> Vec<CompactString> {
+    if bianma.len() < 3 {
+        return Vec::new();
+    }
+
+    (1..bianma.len().clamp(3, 5) - 1)
+        .map(|i| {
+            let mut jianma = CompactString::from(&bianma[..i]);
+            jianma.push(bianma.chars().last().unwrap());
+            jianma
+        })
+        .collect()
 }
 
 fn make_jianma_candidate(
     mabiao: &HashMap<char, Character>,
-    allowed: &Vec<CompactString>,
-    predefineds: &Vec<Predefined>,
-) -> Vec<(char, Character)> {
+    blockers: &HashMap<char, CompactString>,
+    allowed: &[CompactString],
+    predefineds: &[Predefined],
+) -> Vec<Candidate> {
     let mut result = Vec::new();
-    let mut unavailable_bianma = mabiao
+    let mut unavailable_bianma = blockers
         .values()
-        .map(|ch| ch.bianma.clone())
+        .cloned()
         .collect::<HashSet<_>>();
     unavailable_bianma.extend(predefineds.iter().map(|pre| pre.bianma.clone()));
 
@@ -267,10 +810,7 @@ fn make_jianma_candidate(
             continue
         }
 
-        for i in 1..character.bianma.len().clamp(3, 5) - 1 {
-            let mut jianma = CompactString::from(&character.bianma.as_str()[..i]);
-            jianma.push(character.bianma.chars().last().unwrap());
-
+        for jianma in suffix_jianma_codes(&character.bianma) {
             if unavailable_bianma.contains(&jianma)
                 && !allowed.contains(&jianma)
             {
@@ -278,18 +818,29 @@ fn make_jianma_candidate(
             }
 
             let jianma_diff = (character.bianma.len().min(5) - jianma.len()) as f64;
+            let factors = Factors {
+                // length: jianma_diff,
+                length: f64::powf(1.8, jianma_diff - 1.0),
+                zigen: 1.0 + 0.20 * (character.zigen_count.min(3) as f64 - 1.0),
+                space: 1.0,
+                family: 1.0,
+                typo: 1.0,
+            };
             let jianma_weight = character.weight as f64
-                // * jianma_diff
-                * f64::powf(1.8, jianma_diff - 1.0)
-                * (1.0 + 0.20 * (character.zigen_count.min(3) as f64 - 1.0))
+                * factors.length
+                * factors.zigen
+                * factors.space
                 + 0.0;
 
             if jianma_weight > 8000.0 {
-                result.push((*zi, Character {
-                    bianma: CompactString::from(jianma),
-                    weight: jianma_weight as u64,
-                    zigen_count: character.zigen_count,
-                }));
+                result.push(Candidate {
+                    zi: zi.to_compact_string(),
+                    bianma: jianma,
+                    frequency: character.weight,
+                    stage: Stage::Suffix,
+                    factors,
+                    score: jianma_weight as u64,
+                });
             }
         }
     }
@@ -299,19 +850,19 @@ fn make_jianma_candidate(
 
 fn make_space_jianma_candidate(
     mabiao: &HashMap<char, Character>,
-    suffix_jianma: &Vec<(char, Character)>,
-    predefineds: &Vec<Predefined>,
+    suffix_jianma: &[Candidate],
+    predefineds: &[Predefined],
     b_area: &[char],
-) -> Vec<(char, Character)> {
+) -> Vec<Candidate> {
     let mut result = Vec::new();
     let unneeded_zi = suffix_jianma
         .iter()
-        .filter(|(_zi, ch)| ch.bianma.len() <= 3)
-        .map(|(zi, _ch)| *zi)
+        .filter(|cand| cand.bianma.len() <= 3)
+        .map(|cand| cand.zi.clone())
         .chain(predefineds
             .iter()
             .filter(|pre| pre.bianma.len() <= 3 && pre.zi.chars().count() == 1)
-            .map(|pre| pre.zi.chars().next().unwrap())
+            .map(|pre| pre.zi.clone())
         )
         .collect::<HashSet<_>>();
 
@@ -323,11 +874,12 @@ fn make_space_jianma_candidate(
 
     let suffix_jianma_len = suffix_jianma
         .iter()
-        .map(|(zi, ch)| (*zi, ch.bianma.len()))
+        .map(|cand| (cand.zi.clone(), cand.bianma.len()))
         .collect::<HashMap<_, _>>();
 
     for (zi, character) in mabiao.iter() {
-        if unneeded_zi.contains(zi) {
+        let zi_str = zi.to_compact_string();
+        if unneeded_zi.contains(&zi_str) {
             continue
         }
 
@@ -335,7 +887,7 @@ fn make_space_jianma_candidate(
             continue
         }
 
-        let max_jianma_len = if let Some(x) = suffix_jianma_len.get(zi) {
+        let max_jianma_len = if let Some(x) = suffix_jianma_len.get(&zi_str) {
             x - 1
         } else {
             4
@@ -349,19 +901,29 @@ fn make_space_jianma_candidate(
             }
 
             let jianma_diff = (character.bianma.len().min(5) - jianma.len() - 1) as f64;
+            let factors = Factors {
+                // length: jianma_diff,
+                length: f64::powf(1.8, jianma_diff - 1.0),
+                zigen: 1.0 + 0.30 * (character.zigen_count.min(3) as f64 - 1.0),
+                space: f64::powf(10.0, -(jianma.len().saturating_sub(2) as f64)),
+                family: 1.0,
+                typo: 1.0,
+            };
             let jianma_weight = character.weight as f64
-                // * jianma_diff
-                * f64::powf(1.8, jianma_diff - 1.0)
-                * (1.0 + 0.30 * (character.zigen_count.min(3) as f64 - 1.0))
-                * f64::powf(10.0, -(jianma.len().saturating_sub(2) as f64))
+                * factors.length
+                * factors.zigen
+                * factors.space
                 + 0.0;
 
             if jianma_weight > 30000.0 {
-                result.push((*zi, Character {
-                    bianma: CompactString::from(jianma),
-                    weight: jianma_weight as u64,
-                    zigen_count: character.zigen_count,
-                }));
+                result.push(Candidate {
+                    zi: zi_str.clone(),
+                    bianma: jianma,
+                    frequency: character.weight,
+                    stage: Stage::Space,
+                    factors,
+                    score: jianma_weight as u64,
+                });
             }
         }
     }
@@ -369,42 +931,40 @@ fn make_space_jianma_candidate(
     result
 }
 
-fn write_jianma_candidate<W: Write>(writer: W, candidates: &Vec<(char, Character)>) {
-    let mut candidates = candidates.iter()
-        .map(|(zi, character)| {
-            (*zi, character.bianma.clone(), character.weight)
-        })
-        .collect::<Vec<_>>();
-
-    candidates.sort_by(|a, b| a.2.cmp(&b.2).reverse());
+fn sort_candidates(candidates: &[Candidate]) -> Vec<&Candidate> {
+    let mut candidates = candidates.iter().collect::<Vec<_>>();
+    candidates.sort_by(|a, b| a.score.cmp(&b.score).reverse());
+    candidates
+}
 
+fn write_jianma_candidate<W: Write>(writer: W, candidates: &[Candidate]) {
     let mut writer = BufWriter::new(writer);
 
-    for (zi, bianma, weight) in candidates.iter() {
-        write!(writer, "{zi}\t{bianma}\t{weight}\n").unwrap();
+    for cand in sort_candidates(candidates) {
+        writeln!(writer, "{}\t{}\t{}", cand.zi, cand.bianma, cand.score).unwrap();
     }
 }
 
-fn make_jianma_table_lsap(jianma: &Vec<(char, Character)>) -> (u64, Vec<(char, Character)>) {
+fn make_jianma_table_lsap(jianma: &[Candidate]) -> (u64, Vec<Candidate>) {
     let zis = jianma
         .iter()
-        .map(|(zi, _)| *zi)
+        .map(|cand| cand.zi.clone())
         .collect::<HashSet<_>>()
         .into_iter()
         .collect::<Vec<_>>();
     let bianmas = jianma
         .iter()
-        .map(|(_, ch)| ch.bianma.clone())
+        .map(|cand| cand.bianma.clone())
         .collect::<HashSet<_>>()
         .into_iter()
         .collect::<Vec<_>>();
-    let scores = jianma.iter().map(|(zi, ch)| ((zi, &ch.bianma), ch.weight)).collect::<HashMap<_, _>>();
+    let scores = jianma.iter().map(|cand| ((&cand.zi, &cand.bianma), cand)).collect::<HashMap<_, _>>();
 
     let mut cost_matrix = vec![0.0; zis.len() * bianmas.len()];
     for (i, bianma) in bianmas.iter().enumerate() {
         for (j, zi) in zis.iter().enumerate() {
-            if let Some(score) = scores.get(&(zi, bianma)) {
-                cost_matrix[i * zis.len() + j] = *score as f64;
+            if let Some(cand) = scores.get(&(zi, bianma)) {
+                cost_matrix[i * zis.len() + j] = cand.score as f64;
             }
         }
     }
@@ -417,17 +977,10 @@ fn make_jianma_table_lsap(jianma: &Vec<(char, Character)>) -> (u64, Vec<(char, C
     let mut total_score = 0;
 
     for (&i, &j) in optimal.0.iter().zip(optimal.1.iter()) {
-        let i = i as usize;
-        let j = j as usize;
-
         let score = cost_matrix[i * zis.len() + j];
 
         if score > 0.0 {
-            selected_jianma.push((zis[j], Character {
-                bianma: bianmas[i].clone(),
-                weight: score as u64,
-                zigen_count: 0,
-            }));
+            selected_jianma.push(scores[&(&zis[j], &bianmas[i])].clone());
             total_score += score as u64;
         }
     }
@@ -435,25 +988,30 @@ fn make_jianma_table_lsap(jianma: &Vec<(char, Character)>) -> (u64, Vec<(char, C
     (total_score, selected_jianma)
 }
 
-fn write_selected_jianma<W: Write>(
-    writer: W,
-    jianmas: &Vec<(char, Character)>,
-    predefineds: &Vec<Predefined>,
-    additionals: &Vec<Predefined>,
+/// 指派简码，并去掉被同阶段立即上屏的简码挡住的编码：这样的编码不再参与指派，重新求解直到没有冲突。
+fn make_typeable_jianma_table(candidates: &mut Vec<Candidate>, commit: &commit::CommitRules) -> (u64, Vec<Candidate>) {
+    loop {
+        let (score, selected) = make_jianma_table_lsap(candidates);
+        let shadowed = commit.shadowed_within(&selected);
+        if shadowed.is_empty() {
+            return (score, selected);
+        }
+        candidates.retain(|cand| !shadowed.contains(&cand.bianma));
+    }
+}
+
+fn collect_selected_jianma(
+    jianmas: &[Candidate],
+    predefineds: &[Predefined],
+    additionals: &[Predefined],
     b_area: &[char],
     space_jianma: bool,
     sort_by_score: bool,
-) {
-    let mut writer = BufWriter::new(writer);
-
-    let selected_jianma = if !sort_by_score {
+) -> Vec<(CompactString, CompactString)> {
+    let mut selected_jianma: Vec<_> = if !sort_by_score {
         let mut jianmas = jianmas
             .iter()
-            .map(|(zi, ch)| {
-                let mut zi_str = CompactString::new("");
-                zi_str.push(*zi);
-                (zi_str, ch.bianma.clone())
-            })
+            .map(|cand| (cand.zi.clone(), cand.bianma.clone()))
             .collect::<Vec<_>>();
         jianmas.extend(predefineds.iter().map(|pre| (pre.zi.clone(), pre.bianma.clone())));
         jianmas.extend(additionals.iter().map(|pre| (pre.zi.clone(), pre.bianma.clone())));
@@ -470,82 +1028,159 @@ fn write_selected_jianma<W: Write>(
 
         jianmas
     } else {
-        let mut jianmas = jianmas.clone();
-        jianmas.sort_by(|a, b| {
-            a.1.weight.cmp(&b.1.weight).reverse()
-        });
-
-        let jianmas = jianmas.into_iter()
-            .map(|(zi, ch)| {
-                let mut zi_str = CompactString::new("");
-                zi_str.push(zi);
-                (zi_str, ch.bianma.clone())
-            })
+        sort_candidates(jianmas)
+            .into_iter()
+            .map(|cand| (cand.zi.clone(), cand.bianma.clone()))
             .chain(predefineds.iter().map(|pre| (pre.zi.clone(), pre.bianma.clone())))
             .chain(additionals.iter().map(|pre| (pre.zi.clone(), pre.bianma.clone())))
-            .collect();
-
-        jianmas
+            .collect()
     };
 
+    selected_jianma.retain(|(_zi, bianma)| space_jianma || bianma.ends_with(b_area));
+    selected_jianma
+}
+
+fn write_selected_jianma<W: Write>(writer: W, selected_jianma: &[(CompactString, CompactString)]) {
+    let mut writer = BufWriter::new(writer);
+
     for (zi, bianma) in selected_jianma.iter() {
-        if !space_jianma && !bianma.ends_with(b_area) {
-            continue
-        }
-        write!(writer, "{zi}\t{bianma}\n").unwrap();
+        writeln!(writer, "{zi}\t{bianma}").unwrap();
     }
 }
 
-fn main() {
-    let args = argh::from_env::<Args>();
-    let b_area = args.b_area.chars().collect::<Vec<_>>();
-
-    let mut mabiao = String::new();
-    File::open(&args.mabiao)
-        .expect("无法打开码表")
-        .read_to_string(&mut mabiao)
-        .expect("无法读取码表");
+/// 一次完整的简码计算结果。
+struct Assignment {
+    /// 韵码简的候选。
+    candidates: Vec<Candidate>,
+    /// 空格简的候选，未开启空格简时为空。
+    space_candidates: Vec<Candidate>,
+    /// 最终选出的简码，包括韵码简与空格简。
+    selected: Vec<Candidate>,
+    score: u64,
+    score_space: u64,
+}
 
-    let mut mabiao = get_viable_mabiao(&mabiao);
-    initialize_weight(&mut mabiao, &args.frequency);
-    initialize_zigen_count(&mut mabiao, &args.count);
+/// 为一个阶段的候选指派简码，earlier 为之前阶段已选出的简码。
+/// 部件家族奖励与误触惩罚取决于其他字的指派结果，无法直接放进指派问题，因此按成对的近似逐轮求解：
+/// 以上一轮的结果更新各候选的系数，再重新指派，直到结果不再变化。
+fn assign_stage(candidates: &mut Vec<Candidate>, earlier: &[Candidate], inputs: &Inputs) -> (u64, Vec<Candidate>) {
+    let (mut score, mut selected) = make_typeable_jianma_table(candidates, &inputs.commit);
+    let family = !inputs.families.is_empty() && inputs.scoring.family_bonus > 0.0;
+    let typo = inputs.scoring.typo_penalty > 0.0;
+    if !family && !typo {
+        return (score, selected);
+    }
 
-    let alloweds = read_allow_file(&args.allow);
-    let predefineds = read_predefined_file(&args.predefined);
-    let additionals = read_additional_file(&args.additional);
-    let candidates = make_jianma_candidate(&mabiao, &alloweds, &predefineds);
+    let fixed_occupancy = if typo { typo::fixed_occupancy(inputs) } else { typo::Occupancy::new() };
+    for _ in 0..inputs.scoring.pairwise_rounds {
+        let assigned = earlier.iter().chain(selected.iter()).collect::<Vec<_>>();
+        if family {
+            family::update_family_factor(candidates, &assigned, &inputs.families, &inputs.mabiao, inputs.scoring.family_bonus);
+        }
+        if typo {
+            typo::update_typo_factor(candidates, &fixed_occupancy, &assigned, &inputs.layout, inputs.scoring.typo_penalty);
+        }
+        for cand in candidates.iter_mut() {
+            cand.score = cand.factors.score(cand.frequency) as u64;
+        }
 
-    if args.print_candidates {
-        write_jianma_candidate(stdout(), &candidates);
-        return;
+        let previous = selected
+            .iter()
+            .map(|cand| (cand.zi.clone(), cand.bianma.clone()))
+            .collect::<HashSet<_>>();
+        (score, selected) = make_typeable_jianma_table(candidates, &inputs.commit);
+        if selected.len() == previous.len()
+            && selected.iter().all(|cand| previous.contains(&(cand.zi.clone(), cand.bianma.clone())))
+        {
+            break
+        }
     }
 
-    let (score, mut selected_jianma) = make_jianma_table_lsap(&candidates);
+    (score, selected)
+}
+
+/// 计算简码前已被占用的编码：各字的全码、词组的全码，以及预定义与额外定义的编码。
+fn occupied_codes(inputs: &Inputs) -> impl Iterator<Item = &CompactString> {
+    inputs.blockers
+        .values()
+        .chain(inputs.words.iter().map(|word| &word.bianma))
+        .chain(inputs.predefineds.iter().map(|pre| &pre.bianma))
+        .chain(inputs.additionals.iter().map(|pre| &pre.bianma))
+}
+
+/// 韵码简的候选：单字与词组，已去掉保留编码，以及按上屏规则无法输入的编码。
+fn make_all_jianma_candidate(inputs: &Inputs) -> Vec<Candidate> {
+    let mut candidates = make_jianma_candidate(
+        &inputs.mabiao, &inputs.blockers, &inputs.alloweds, &inputs.predefineds
+    );
+    candidates.extend(word::make_word_jianma_candidate(
+        &inputs.words, &inputs.blockers, &inputs.alloweds, &inputs.predefineds, &inputs.b_area
+    ));
+    inputs.reserved.retain_unreserved(&mut candidates);
+    inputs.commit.retain_typeable(&mut candidates, occupied_codes(inputs));
+    candidates
+}
 
-    let score_space = if args.space_jianma {
-        let candidates = make_space_jianma_candidate(
-            &mabiao, &selected_jianma, &predefineds, &b_area
+fn assign_jianma(inputs: &Inputs, space_jianma: bool) -> Assignment {
+    let mut candidates = make_all_jianma_candidate(inputs);
+    let (score, mut selected) = assign_stage(&mut candidates, &[], inputs);
+
+    let (score_space, space_candidates) = if space_jianma {
+        let mut candidates = make_space_jianma_candidate(
+            &inputs.mabiao, &selected, &inputs.predefineds, &inputs.b_area
+        );
+        candidates.extend(word::make_word_space_jianma_candidate(
+            &inputs.words, &selected, &inputs.predefineds, &inputs.b_area
+        ));
+        inputs.reserved.retain_unreserved(&mut candidates);
+        inputs.commit.retain_typeable(
+            &mut candidates,
+            occupied_codes(inputs).chain(selected.iter().map(|cand| &cand.bianma)),
         );
-        let (score, jianma) = make_jianma_table_lsap(&candidates);
-        selected_jianma.extend_from_slice(&jianma);
-        score
+        let (score, jianma) = assign_stage(&mut candidates, &selected, inputs);
+        selected.extend_from_slice(&jianma);
+        (score, candidates)
     } else {
-        0
+        (0, Vec::new())
     };
 
-    write_selected_jianma(
-        File::create(&args.out).expect("无法创建简码表文件"),
-        &selected_jianma,
-        &predefineds,
-        &additionals,
-        &b_area,
-        args.space_jianma,
-        args.sort_freq,
-    );
+    Assignment {
+        candidates,
+        space_candidates,
+        selected,
+        score,
+        score_space,
+    }
+}
 
-    println!("最终简码得分：");
-    println!("韵码简\t\t{} 分", score);
-    if args.space_jianma {
-        println!("韵码加空格简\t{} 分", score + score_space);
+fn main() {
+    let args = argh::from_env::<Args>();
+
+    // 各子命令只在需要时读取输入。
+    match &args.command {
+        Command::Generate(cmd) => generate::run_generate(cmd, &load_scored_inputs(&args, cmd.scoring())),
+        Command::Candidates(cmd) => generate::run_candidates(cmd, &load_scored_inputs(&args, cmd.scoring())),
+        Command::Evaluate(cmd) => evaluate::run(stdout(), cmd, &load_scored_inputs(&args, cmd.scoring())),
+        Command::Diff(cmd) => {
+            let (mabiao, commit) = load_diff_inputs(&args);
+            diff::run(stdout(), cmd, &mabiao, &commit)
+        }
+        Command::Validate(cmd) => validate::run(stdout(), cmd, &read_inputs(&args)),
+        Command::Explain(cmd) => explain::run(stdout(), cmd, &load_inputs(&args)),
+        Command::SuggestAllow(cmd) => suggest::run_allow(stdout(), cmd, &load_inputs(&args)),
+        Command::SuggestPredefined(cmd) => suggest::run_predefined(stdout(), cmd, &load_inputs(&args)),
+        Command::Freq(cmd) => {
+            freq::run(stdout(), cmd, || read_mabiao(&args.mabiao).into_keys().collect(), || load_inputs(&args))
+        }
+        Command::Profiles(cmd) => {
+            let inputs = load_inputs(&args);
+            profile::run(stdout(), cmd, |frequency| load_profile(&args, &inputs, frequency))
+        }
+        Command::Isomorph(cmd) => isomorph::run(stdout(), cmd, &load_inputs(&args)),
+        Command::Collisions(cmd) => collision::run(stdout(), cmd, &load_inputs(&args)),
+        Command::OptimizeLayout(cmd) => layout::run(stdout(), cmd, &load_inputs(&args)),
+        Command::Typos(cmd) => typo::run(stdout(), cmd, &load_inputs(&args)),
+        Command::Reserved(cmd) => reserve::run(stdout(), cmd, &load_inputs(&args)),
+        Command::Shadows(cmd) => commit::run(stdout(), cmd, &load_inputs(&args)),
     }
 }
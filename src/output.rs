@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use compact_str::CompactString;
+use serde::Serialize;
+
+use crate::{Candidate, Character, Factors, Predefined, Stage};
+
+pub struct DictOptions {
+    name: String,
+    version: String,
+    sort: String,
+    import_tables: Vec<String>,
+}
+
+impl DictOptions {
+    /// Rime 码表的排序方式默认为 by_weight，可用 `with_rime_sort` 指定。
+    pub fn new(out: &Path, name: &str, version: &str, import_tables: &str) -> Self {
+        let name = if name.is_empty() {
+            let file_name = out
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            file_name
+                .strip_suffix(".dict.yaml")
+                .or_else(|| file_name.rsplit_once('.').map(|(stem, _ext)| stem))
+                .unwrap_or(&file_name)
+                .to_string()
+        } else {
+            name.to_string()
+        };
+
+        let import_tables = import_tables
+            .split(',')
+            .map(|table| table.trim())
+            .filter(|table| !table.is_empty())
+            .map(String::from)
+            .collect();
+
+        Self {
+            name,
+            version: version.to_string(),
+            sort: String::from("by_weight"),
+            import_tables,
+        }
+    }
+
+    /// 指定 Rime 码表的排序方式，只能是 by_weight 或 original。其他格式的码表不用排序方式。
+    pub fn with_rime_sort(self, sort: &str) -> Result<Self, String> {
+        if sort != "by_weight" && sort != "original" {
+            return Err(format!("未知的 Rime 码表排序方式：{sort}，只能是 by_weight 或 original"));
+        }
+
+        Ok(Self { sort: sort.to_string(), ..self })
+    }
+}
+
+/// 为简码表中的每个条目附上权重。单字取其字频；词组或不在码表中的字视作手工指定的编码，
+/// 取码表中的最高字频，使之排在同码的全码字之前。
+pub fn weighted_entries(
+    selected_jianma: &[(CompactString, CompactString)],
+    mabiao: &HashMap<char, Character>,
+) -> Vec<(CompactString, CompactString, u64)> {
+    let max_weight = mabiao.values().map(|ch| ch.weight).max().unwrap_or(1);
+
+    selected_jianma
+        .iter()
+        .map(|(zi, bianma)| {
+            let mut chars = zi.chars();
+            let weight = match (chars.next(), chars.next()) {
+                (Some(ch), None) => mabiao.get(&ch).map(|c| c.weight).unwrap_or(max_weight),
+                _ => max_weight,
+            };
+            (zi.clone(), bianma.clone(), weight)
+        })
+        .collect()
+}
+
+/// 把码表中所有字的全码并入简码表，使输出的码表可以独立使用。
+/// 不在字频表中的字权重为 0。
+pub fn merge_full_codes(
+    entries: &mut Vec<(CompactString, CompactString, u64)>,
+    full_mabiao: &HashMap<char, Character>,
+    mabiao: &HashMap<char, Character>,
+) {
+    let existing = entries
+        .iter()
+        .map(|(zi, bianma, _)| (zi.clone(), bianma.clone()))
+        .collect::<HashSet<_>>();
+
+    let mut full_codes = full_mabiao
+        .iter()
+        .map(|(zi, character)| {
+            let mut zi_str = CompactString::new("");
+            zi_str.push(*zi);
+            let weight = mabiao.get(zi).map(|c| c.weight).unwrap_or(0);
+            (zi_str, character.bianma.clone(), weight)
+        })
+        .filter(|(zi, bianma, _)| !existing.contains(&(zi.clone(), bianma.clone())))
+        .collect::<Vec<_>>();
+
+    full_codes.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.cmp(&b.2).reverse()));
+    entries.extend(full_codes);
+}
+
+/// 码表用到的所有键，以及键盘布局中的编码键。
+fn key_code(entries: &[(CompactString, CompactString, u64)], code_keys: &[char]) -> String {
+    entries
+        .iter()
+        .flat_map(|(_, bianma, _)| bianma.chars())
+        .chain(code_keys.iter().copied())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn max_code_length(entries: &[(CompactString, CompactString, u64)]) -> usize {
+    entries.iter().map(|(_, bianma, _)| bianma.len()).max().unwrap_or(4)
+}
+
+/// 按编码排序，同码者按权重降序排列。
+fn sorted_by_code(
+    entries: &[(CompactString, CompactString, u64)],
+) -> Vec<&(CompactString, CompactString, u64)> {
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.cmp(&b.2).reverse()));
+    entries
+}
+
+pub fn write_rime_dict<W: Write>(
+    writer: W,
+    entries: &[(CompactString, CompactString, u64)],
+    options: &DictOptions,
+) {
+    let mut writer = BufWriter::new(writer);
+
+    writeln!(writer, "# Rime dictionary").unwrap();
+    writeln!(writer, "# encoding: utf-8").unwrap();
+    writeln!(writer, "#").unwrap();
+    writeln!(writer, "# 由 makejian 生成的简码表").unwrap();
+    writeln!(writer).unwrap();
+    writeln!(writer, "---").unwrap();
+    writeln!(writer, "name: {}", options.name).unwrap();
+    writeln!(writer, "version: \"{}\"", options.version).unwrap();
+    writeln!(writer, "sort: {}", options.sort).unwrap();
+    writeln!(writer, "columns:").unwrap();
+    writeln!(writer, "  - text").unwrap();
+    writeln!(writer, "  - code").unwrap();
+    writeln!(writer, "  - weight").unwrap();
+    if !options.import_tables.is_empty() {
+        writeln!(writer, "import_tables:").unwrap();
+        for table in options.import_tables.iter() {
+            writeln!(writer, "  - {table}").unwrap();
+        }
+    }
+    writeln!(writer, "...").unwrap();
+    writeln!(writer).unwrap();
+
+    for (zi, bianma, weight) in entries.iter() {
+        writeln!(writer, "{zi}\t{bianma}\t{weight}").unwrap();
+    }
+}
+
+/// 输出 libime（fcitx5-table）的文本码表，可用 libime_tabledict 转换为二进制码表。
+/// rules 为词组的构词规则，见 `CodingRule::table_rules`；code_keys 为键盘布局中的编码键。
+pub fn write_libime_table<W: Write>(
+    writer: W,
+    entries: &[(CompactString, CompactString, u64)],
+    rules: &[(String, String)],
+    code_keys: &[char],
+) {
+    let mut writer = BufWriter::new(writer);
+
+    writeln!(writer, "KeyCode={}", key_code(entries, code_keys)).unwrap();
+    writeln!(writer, "Length={}", max_code_length(entries)).unwrap();
+    writeln!(writer, "[Rule]").unwrap();
+    for (name, rule) in rules.iter() {
+        writeln!(writer, "{name}={rule}").unwrap();
+    }
+    writeln!(writer, "[Data]").unwrap();
+
+    for (zi, bianma, _weight) in sorted_by_code(entries) {
+        writeln!(writer, "{bianma} {zi}").unwrap();
+    }
+}
+
+/// 输出 ibus-table 的码表源文件，可用 ibus-table-createdb 转换为数据库。
+pub fn write_ibus_table<W: Write>(
+    writer: W,
+    entries: &[(CompactString, CompactString, u64)],
+    options: &DictOptions,
+    rules: &[(String, String)],
+    code_keys: &[char],
+) {
+    let mut writer = BufWriter::new(writer);
+    let rules = rules
+        .iter()
+        .map(|(name, rule)| format!("c{name}:{rule}"))
+        .collect::<Vec<_>>()
+        .join(";");
+
+    writeln!(writer, "### 由 makejian 生成的 ibus-table 码表").unwrap();
+    writeln!(writer, "BEGIN_DEFINITION").unwrap();
+    writeln!(writer, "NAME = {}", options.name).unwrap();
+    writeln!(writer, "SERIAL_NUMBER = {}", options.version).unwrap();
+    writeln!(writer, "LANGUAGES = zh_CN,zh_SG,zh_TW,zh_HK").unwrap();
+    writeln!(writer, "LAYOUT = us").unwrap();
+    writeln!(writer, "VALID_INPUT_CHARS = {}", key_code(entries, code_keys)).unwrap();
+    writeln!(writer, "MAX_KEY_LENGTH = {}", max_code_length(entries)).unwrap();
+    writeln!(writer, "RULES = {rules}").unwrap();
+    writeln!(writer, "END_DEFINITION").unwrap();
+    writeln!(writer).unwrap();
+    writeln!(writer, "BEGIN_TABLE").unwrap();
+
+    for (zi, bianma, weight) in sorted_by_code(entries) {
+        writeln!(writer, "{bianma}\t{zi}\t{weight}").unwrap();
+    }
+
+    writeln!(writer, "END_TABLE").unwrap();
+}
+
+/// 简码的来源。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// 由本程序计算得出。
+    Computed,
+    /// 来自预定义编码表。
+    Predefined,
+    /// 来自额外定义编码表。
+    Additional,
+}
+
+/// JSON/JSONL 输出中的一条简码记录。
+#[derive(Debug, Clone, Serialize)]
+pub struct JianmaRecord<'a> {
+    /// 汉字或词组。
+    zi: CompactString,
+    /// 简码。
+    bianma: &'a str,
+    /// 简码阶段：suffix（韵码简）或 space（空格简）。
+    stage: Stage,
+    /// 简码来源：computed、predefined 或 additional。
+    source: Source,
+    /// 混合后的字频，词组或不在字频表中的字为 0。
+    frequency: u64,
+    /// 各字频表对 frequency 的贡献。
+    corpora: BTreeMap<&'a str, u64>,
+    /// 得分的各项系数，仅 computed 的简码才有。
+    factors: Option<&'a Factors>,
+    /// 最终得分，仅 computed 的简码才有。
+    score: Option<u64>,
+}
+
+fn frequency_of<'a>(
+    zi: &str,
+    mabiao: &'a HashMap<char, Character>,
+    corpora: &'a [CompactString],
+) -> (u64, BTreeMap<&'a str, u64>) {
+    let mut chars = zi.chars();
+    let character = match (chars.next(), chars.next()) {
+        (Some(ch), None) => mabiao.get(&ch),
+        _ => None,
+    };
+
+    match character {
+        Some(character) => {
+            let contributions = corpora
+                .iter()
+                .map(|name| name.as_str())
+                .zip(character.frequencies.iter().copied())
+                .collect();
+            (character.weight, contributions)
+        }
+        None => (0, BTreeMap::new()),
+    }
+}
+
+fn stage_of(bianma: &str, b_area: &[char]) -> Stage {
+    if bianma.ends_with(b_area) {
+        Stage::Suffix
+    } else {
+        Stage::Space
+    }
+}
+
+pub fn candidate_records<'a>(
+    candidates: &[&'a Candidate],
+    mabiao: &'a HashMap<char, Character>,
+    corpora: &'a [CompactString],
+) -> Vec<JianmaRecord<'a>> {
+    candidates
+        .iter()
+        .map(|cand| {
+            let (_, corpora) = frequency_of(&cand.zi, mabiao, corpora);
+            JianmaRecord {
+                zi: cand.zi.clone(),
+                bianma: cand.bianma.as_str(),
+                stage: cand.stage,
+                source: Source::Computed,
+                frequency: cand.frequency,
+                corpora,
+                factors: Some(&cand.factors),
+                score: Some(cand.score),
+            }
+        })
+        .collect()
+}
+
+/// 为最终简码表中的每个条目找出其来源，生成 JSON 记录。
+pub fn selected_records<'a>(
+    selected_jianma: &'a [(CompactString, CompactString)],
+    jianmas: &'a [Candidate],
+    predefineds: &[Predefined],
+    mabiao: &'a HashMap<char, Character>,
+    corpora: &'a [CompactString],
+    b_area: &[char],
+) -> Vec<JianmaRecord<'a>> {
+    let computed = jianmas
+        .iter()
+        .map(|cand| ((cand.zi.as_str(), cand.bianma.as_str()), cand))
+        .collect::<HashMap<_, _>>();
+    let predefineds = predefineds
+        .iter()
+        .map(|pre| (pre.zi.as_str(), pre.bianma.as_str()))
+        .collect::<HashSet<_>>();
+
+    selected_jianma
+        .iter()
+        .map(|(zi, bianma)| {
+            let (frequency, contributions) = frequency_of(zi, mabiao, corpora);
+            let candidate = computed.get(&(zi.as_str(), bianma.as_str()));
+            let frequency = candidate.map(|cand| cand.frequency).unwrap_or(frequency);
+
+            let (stage, source, factors, score) = match candidate {
+                Some(cand) => (cand.stage, Source::Computed, Some(&cand.factors), Some(cand.score)),
+                None if predefineds.contains(&(zi.as_str(), bianma.as_str())) => {
+                    (stage_of(bianma, b_area), Source::Predefined, None, None)
+                }
+                None => (stage_of(bianma, b_area), Source::Additional, None, None),
+            };
+
+            JianmaRecord {
+                zi: zi.clone(),
+                bianma: bianma.as_str(),
+                stage,
+                source,
+                frequency,
+                corpora: contributions,
+                factors,
+                score,
+            }
+        })
+        .collect()
+}
+
+/// 输出 JSON 记录。jsonl 为真时一行一条记录，否则输出一个 JSON 数组。
+pub fn write_json_records<W: Write>(writer: W, records: &[JianmaRecord], jsonl: bool) {
+    let mut writer = BufWriter::new(writer);
+
+    if jsonl {
+        for record in records.iter() {
+            serde_json::to_writer(&mut writer, record).expect("无法输出JSON");
+            writeln!(writer).unwrap();
+        }
+    } else {
+        serde_json::to_writer_pretty(&mut writer, records).expect("无法输出JSON");
+        writeln!(writer).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(CompactString, CompactString, u64)> {
+        vec![
+            (CompactString::from("的"), CompactString::from("e"), 100),
+            (CompactString::from("一下"), CompactString::from("yx"), 100),
+        ]
+    }
+
+    #[test]
+    fn dict_name_from_output_path() {
+        let options = DictOptions::new(Path::new("output/yuming_jianma.dict.yaml"), "", "1.0", "");
+        assert_eq!(options.name, "yuming_jianma");
+        assert_eq!(options.sort, "by_weight");
+
+        let options = DictOptions::new(Path::new("output/yuming.txt"), "", "1.0", "");
+        assert_eq!(options.name, "yuming");
+
+        let options = DictOptions::new(Path::new("output/yuming.txt"), "jianma", "2.0", " a, ,b ");
+        assert_eq!(options.name, "jianma");
+        assert_eq!(options.version, "2.0");
+        assert_eq!(options.import_tables, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn rime_sort() {
+        let options = || DictOptions::new(Path::new("a.dict.yaml"), "", "1.0", "");
+        assert_eq!(options().with_rime_sort("original").unwrap().sort, "original");
+        assert_eq!(options().with_rime_sort("by_weight").unwrap().sort, "by_weight");
+        assert!(options().with_rime_sort("by_code").is_err());
+    }
+
+    #[test]
+    fn rime_header() {
+        let options = DictOptions::new(Path::new("yuming_jianma.dict.yaml"), "", "1.0", "yuming_chaifen")
+            .with_rime_sort("original")
+            .unwrap();
+        let mut out = Vec::new();
+        write_rime_dict(&mut out, &entries(), &options);
+
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            "# Rime dictionary\n",
+            "# encoding: utf-8\n",
+            "#\n",
+            "# 由 makejian 生成的简码表\n",
+            "\n",
+            "---\n",
+            "name: yuming_jianma\n",
+            "version: \"1.0\"\n",
+            "sort: original\n",
+            "columns:\n",
+            "  - text\n",
+            "  - code\n",
+            "  - weight\n",
+            "import_tables:\n",
+            "  - yuming_chaifen\n",
+            "...\n",
+            "\n",
+            "的\te\t100\n",
+            "一下\tyx\t100\n",
+        ));
+    }
+
+    fn character(bianma: &str, weight: u64) -> Character {
+        Character { bianma: CompactString::from(bianma), weight, zigen_count: 1, frequencies: vec![weight] }
+    }
+
+    fn table_rules() -> Vec<(String, String)> {
+        crate::rule::DEFAULT_WORD_RULE.parse::<crate::rule::CodingRule>().unwrap().table_rules()
+    }
+
+    #[test]
+    fn weights_and_merged_full_codes() {
+        let mabiao = HashMap::from([('的', character("ekoe", 100)), ('了', character("lkae", 50))]);
+        let full_mabiao = HashMap::from([
+            ('的', character("ekoe", 0)),
+            ('了', character("lkae", 0)),
+            ('乜', character("lkae", 0)),
+        ]);
+        let selected = vec![
+            (CompactString::from("的"), CompactString::from("e")),
+            (CompactString::from("一下"), CompactString::from("yx")),
+            (CompactString::from("了"), CompactString::from("lkae")),
+        ];
+
+        let mut entries = weighted_entries(&selected, &mabiao);
+        assert_eq!(entries.iter().map(|entry| entry.2).collect::<Vec<_>>(), vec![100, 100, 50]);
+
+        merge_full_codes(&mut entries, &full_mabiao, &mabiao);
+        let merged = entries[3..]
+            .iter()
+            .map(|(zi, bianma, weight)| (zi.as_str(), bianma.as_str(), *weight))
+            .collect::<Vec<_>>();
+        assert_eq!(merged, vec![("的", "ekoe", 100), ("乜", "lkae", 0)]);
+    }
+
+    #[test]
+    fn libime_header() {
+        let mut out = Vec::new();
+        write_libime_table(&mut out, &entries(), &table_rules(), &['a', 'b']);
+
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            "KeyCode=abexy\n",
+            "Length=2\n",
+            "[Rule]\n",
+            "e2=p11+p12+p21+p22\n",
+            "e3=p11+p21+p31+p32\n",
+            "a4=p11+p21+p31+n11\n",
+            "[Data]\n",
+            "e 的\n",
+            "yx 一下\n",
+        ));
+    }
+
+    #[test]
+    fn ibus_header() {
+        let options = DictOptions::new(Path::new("yuming.ibus.txt"), "", "20250101", "");
+        let mut out = Vec::new();
+        write_ibus_table(&mut out, &entries(), &options, &table_rules(), &['a', 'b']);
+
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            "### 由 makejian 生成的 ibus-table 码表\n",
+            "BEGIN_DEFINITION\n",
+            "NAME = yuming.ibus\n",
+            "SERIAL_NUMBER = 20250101\n",
+            "LANGUAGES = zh_CN,zh_SG,zh_TW,zh_HK\n",
+            "LAYOUT = us\n",
+            "VALID_INPUT_CHARS = abexy\n",
+            "MAX_KEY_LENGTH = 2\n",
+            "RULES = ce2:p11+p12+p21+p22;ce3:p11+p21+p31+p32;ca4:p11+p21+p31+n11\n",
+            "END_DEFINITION\n",
+            "\n",
+            "BEGIN_TABLE\n",
+            "e\t的\t100\n",
+            "yx\t一下\t100\n",
+            "END_TABLE\n",
+        ));
+    }
+}
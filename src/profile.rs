@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use argh::FromArgs;
+use compact_str::CompactString;
+
+use crate::{Inputs, assign_jianma, collect_selected_jianma, write_selected_jianma};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "profiles")]
+/// 在一次运行中按多组字频表分别计算简码（如简体与繁体），共用码表、可用编码表与预定义编码表，
+/// 并列出各组之间不同的简码。
+pub struct ProfilesArgs {
+    #[argh(option)]
+    /// 一组字频表，写法为 名称=字频表，字频表的写法与 --frequency 相同，如 jian=beiyu:0.5,zhihu:0.5。
+    /// 可以重复指定多组。
+    profile: Vec<String>,
+
+    #[argh(option, default = r#"PathBuf::from("output")"#)]
+    /// 简码表的输出目录，每组输出为 名称.txt。默认为 output。
+    out_dir: PathBuf,
+
+    #[argh(switch)]
+    /// 允许空格简码。
+    space_jianma: bool,
+
+    #[argh(switch)]
+    /// 按频率排序生成出来的简码表。
+    sort_freq: bool,
+
+    #[argh(option, default = "50")]
+    /// 最多列出的不同简码数，0 表示全部列出。默认为 50。
+    limit: usize,
+}
+
+/// 一组字频表算出的结果。
+struct ProfileResult {
+    name: CompactString,
+    inputs: Inputs,
+    /// 编码到字的对应。
+    table: BTreeMap<CompactString, Vec<CompactString>>,
+}
+
+fn parse_profile(profile: &str) -> (&str, &str) {
+    match profile.split_once('=') {
+        Some((name, frequency)) if !name.trim().is_empty() => (name.trim(), frequency.trim()),
+        _ => panic!("字频表组 {profile} 缺少名称，应写作 名称=字频表"),
+    }
+}
+
+fn describe(result: &ProfileResult, bianma: &str) -> String {
+    let Some(zis) = result.table.get(bianma) else {
+        return String::from("（无）");
+    };
+
+    zis.iter()
+        .map(|zi| {
+            let mut chars = zi.chars();
+            let weight = match (chars.next(), chars.next()) {
+                (Some(ch), None) => result.inputs.mabiao.get(&ch).map(|ch| ch.weight),
+                _ => None,
+            };
+            match weight {
+                Some(weight) => format!("{zi}（{weight}）"),
+                None => zi.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("、")
+}
+
+pub fn run<W: Write>(writer: W, args: &ProfilesArgs, load: impl Fn(&str) -> Inputs) {
+    let mut writer = BufWriter::new(writer);
+
+    if args.profile.is_empty() {
+        panic!("请用 --profile 指定至少一组字频表");
+    }
+    std::fs::create_dir_all(&args.out_dir).expect("无法创建输出目录");
+
+    let mut results = Vec::new();
+    for profile in args.profile.iter() {
+        let (name, frequency) = parse_profile(profile);
+        let inputs = load(frequency);
+        let assignment = assign_jianma(&inputs, args.space_jianma);
+        let selected = collect_selected_jianma(
+            &assignment.selected,
+            &inputs.predefineds,
+            &inputs.additionals,
+            &inputs.b_area,
+            args.space_jianma,
+            args.sort_freq,
+        );
+
+        let path = args.out_dir.join(format!("{name}.txt"));
+        write_selected_jianma(File::create(&path).expect("无法创建简码表文件"), &selected);
+
+        let score = assignment.score + assignment.score_space;
+        writeln!(writer, "{name}\t{} 条\t{score} 分\t{}", selected.len(), path.display()).unwrap();
+
+        let mut table = BTreeMap::<CompactString, Vec<CompactString>>::new();
+        for (zi, bianma) in selected {
+            table.entry(bianma).or_default().push(zi);
+        }
+        for zis in table.values_mut() {
+            zis.sort();
+        }
+
+        results.push(ProfileResult { name: CompactString::new(name), inputs, table });
+    }
+
+    if results.len() < 2 {
+        return;
+    }
+
+    let codes = results
+        .iter()
+        .flat_map(|result| result.table.keys())
+        .collect::<BTreeSet<_>>();
+    let mut differing = codes
+        .into_iter()
+        .filter(|bianma| {
+            let first = results[0].table.get(*bianma);
+            results.iter().skip(1).any(|result| result.table.get(*bianma) != first)
+        })
+        .collect::<Vec<_>>();
+    differing.sort_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)));
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "各组不同的简码（{} 个，括号内为该组的字频）：", differing.len()).unwrap();
+    let header = results.iter().map(|result| result.name.as_str()).collect::<Vec<_>>().join("\t");
+    writeln!(writer, "编码\t{header}").unwrap();
+
+    let limit = if args.limit == 0 { differing.len() } else { args.limit };
+    for bianma in differing.iter().take(limit) {
+        let row = results
+            .iter()
+            .map(|result| describe(result, bianma))
+            .collect::<Vec<_>>()
+            .join("\t");
+        writeln!(writer, "{bianma}\t{row}").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_named_profiles() {
+        assert_eq!(parse_profile("jian=beiyu:0.5,zhihu:0.5"), ("jian", "beiyu:0.5,zhihu:0.5"));
+        assert_eq!(
+            parse_profile(" fan = tw:1,beiyu@opencc/STCharacters.txt:0.2"),
+            ("fan", "tw:1,beiyu@opencc/STCharacters.txt:0.2"),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "缺少名称")]
+    fn profile_without_name() {
+        parse_profile("beiyu:0.5");
+    }
+
+    #[test]
+    #[should_panic(expected = "缺少名称")]
+    fn profile_with_empty_name() {
+        parse_profile(" =beiyu:0.5");
+    }
+}
@@ -0,0 +1,254 @@
+//! 保留编码：输入法方案另作他用（如 z 引导反查、` 引导通配）或留待将来使用的编码，
+//! 不参与任何阶段的简码计算。一行一个模式：类型与模式，用空白隔开。
+//! 类型为 prefix（以之开头的编码）、glob（? 匹配一码，* 匹配任意码）或 regex（正则表达式）。
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use argh::FromArgs;
+use regex::Regex;
+
+use crate::{Assignment, Candidate, Inputs, assign_jianma, read_file};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "reserved")]
+/// 列出因 --reserved 中的保留编码而改变的简码：不保留时本可得到的简码、所匹配的模式，以及保留后的结果。
+pub struct ReservedArgs {
+    #[argh(switch)]
+    /// 计算简码时允许空格简码。
+    space_jianma: bool,
+
+    #[argh(option, default = "50")]
+    /// 最多列出的条数，0 表示全部列出。默认为 50。
+    top: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    /// 模式在文件中的写法。
+    source: String,
+    regex: Regex,
+}
+
+/// 把 glob 模式转为整个编码都须匹配的正则表达式。
+fn glob_to_regex(glob: &str) -> String {
+    let body = glob
+        .chars()
+        .map(|c| match c {
+            '?' => String::from("."),
+            '*' => String::from(".*"),
+            c => regex::escape(&c.to_string()),
+        })
+        .collect::<String>();
+    format!("^{body}$")
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Reserved {
+    patterns: Vec<Pattern>,
+}
+
+impl Reserved {
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// 编码所匹配的第一个模式。
+    pub fn matching(&self, bianma: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|pattern| pattern.regex.is_match(bianma))
+            .map(|pattern| pattern.source.as_str())
+    }
+
+    pub fn contains(&self, bianma: &str) -> bool {
+        self.matching(bianma).is_some()
+    }
+
+    /// 去掉编码为保留编码的候选。
+    pub fn retain_unreserved(&self, candidates: &mut Vec<Candidate>) {
+        if !self.is_empty() {
+            candidates.retain(|cand| !self.contains(&cand.bianma));
+        }
+    }
+}
+
+pub fn read_reserved(path: &Path) -> Reserved {
+    let mut patterns = Vec::new();
+
+    read_file(path, "保留编码表", |line_no, line| {
+        let Some((kind, pattern)) = line.split_once(char::is_whitespace) else {
+            panic!("保留编码表第{}行缺少类型或模式", line_no + 1);
+        };
+        let pattern = pattern.trim();
+
+        let regex = match kind {
+            "prefix" => format!("^{}", regex::escape(pattern)),
+            "glob" => glob_to_regex(pattern),
+            "regex" => pattern.to_string(),
+            _ => panic!("保留编码表第{}行的类型无效：{kind}", line_no + 1),
+        };
+        let regex = Regex::new(&regex)
+            .unwrap_or_else(|e| panic!("保留编码表第{}行的模式无效：{e}", line_no + 1));
+
+        patterns.push(Pattern { source: format!("{kind} {pattern}"), regex });
+    });
+
+    Reserved { patterns }
+}
+
+/// 各字（或词组）得到的简码，按编码排列。
+fn jianma_by_zi(selected: &[Candidate]) -> HashMap<&str, Vec<&str>> {
+    let mut result = HashMap::<&str, Vec<&str>>::new();
+    for cand in selected.iter() {
+        result.entry(cand.zi.as_str()).or_default().push(cand.bianma.as_str());
+    }
+    for codes in result.values_mut() {
+        codes.sort();
+    }
+    result
+}
+
+pub fn run<W: Write>(writer: W, args: &ReservedArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+
+    if inputs.reserved.is_empty() {
+        panic!("请用 --reserved 指定保留编码表");
+    }
+
+    let unreserved_inputs = Inputs { reserved: Reserved::default(), ..inputs.clone() };
+    let unreserved = assign_jianma(&unreserved_inputs, args.space_jianma);
+    let reserved = assign_jianma(inputs, args.space_jianma);
+    let after = jianma_by_zi(&reserved.selected);
+
+    let mut displaced = unreserved.selected
+        .iter()
+        .filter_map(|cand| inputs.reserved.matching(&cand.bianma).map(|pattern| (cand, pattern)))
+        .collect::<Vec<_>>();
+    displaced.sort_by(|a, b| a.0.score.cmp(&b.0.score).reverse().then(a.0.bianma.cmp(&b.0.bianma)));
+
+    let mut by_pattern = BTreeMap::<&str, usize>::new();
+    for (_, pattern) in displaced.iter() {
+        *by_pattern.entry(pattern).or_default() += 1;
+    }
+
+    // 保留编码占去的简码会让出别的编码，连带改变其他字的简码。
+    let before = jianma_by_zi(&unreserved.selected);
+    let changed = before
+        .keys()
+        .chain(after.keys())
+        .filter(|zi| before.get(*zi) != after.get(*zi))
+        .collect::<HashSet<_>>()
+        .len();
+
+    let score = |assignment: &Assignment| assignment.score + assignment.score_space;
+    writeln!(writer, "不保留时得分：{}", score(&unreserved)).unwrap();
+    writeln!(writer, "保留后得分：{}", score(&reserved)).unwrap();
+    writeln!(writer, "被保留编码挤掉的简码：{} 条，简码有变化的字与词组：{changed} 个", displaced.len()).unwrap();
+    for (pattern, count) in by_pattern.iter() {
+        writeln!(writer, "  {pattern}\t{count} 条").unwrap();
+    }
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "被挤掉的简码（字、原简码、得分、所匹配的模式、保留后的简码）：").unwrap();
+    let limit = if args.top == 0 { displaced.len() } else { args.top };
+    for (cand, pattern) in displaced.iter().take(limit) {
+        let now = after
+            .get(cand.zi.as_str())
+            .map(|codes| codes.join(" "))
+            .unwrap_or_else(|| String::from("无"));
+        writeln!(writer, "{}\t{}\t{}\t{pattern}\t{now}", cand.zi, cand.bianma, cand.score).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use compact_str::CompactString;
+
+    use super::*;
+    use crate::{Factors, Stage};
+
+    fn read(name: &str, content: &str) -> Reserved {
+        let path = std::env::temp_dir().join(format!("makejian-{}-reserved-{name}.txt", std::process::id()));
+        fs::write(&path, content).unwrap();
+        let reserved = read_reserved(&path);
+        fs::remove_file(&path).unwrap();
+        reserved
+    }
+
+    #[test]
+    fn read_patterns() {
+        let reserved = read("ok", concat!(
+            "# 反查\n",
+            "prefix z\n",
+            "\n",
+            "glob\t?v*\n",
+            "regex   ^[aeiou]{2}$\n",
+            "glob a.b\n",
+        ));
+        assert_eq!(reserved.patterns.len(), 4);
+
+        assert_eq!(reserved.matching("z"), Some("prefix z"));
+        assert_eq!(reserved.matching("zab"), Some("prefix z"));
+        assert_eq!(reserved.matching("az"), None);
+
+        assert_eq!(reserved.matching("av"), Some("glob ?v*"));
+        assert_eq!(reserved.matching("avab"), Some("glob ?v*"));
+        assert_eq!(reserved.matching("v"), None);
+        assert_eq!(reserved.matching("aav"), None);
+
+        assert_eq!(reserved.matching("ae"), Some("regex ^[aeiou]{2}$"));
+        assert_eq!(reserved.matching("aei"), None);
+
+        // glob 中的其他字符按字面匹配。
+        assert!(reserved.contains("a.b"));
+        assert!(!reserved.contains("acb"));
+    }
+
+    #[test]
+    fn first_matching_pattern_wins() {
+        let reserved = read("order", "prefix a\nregex ^ae$\n");
+        assert_eq!(reserved.matching("ae"), Some("prefix a"));
+    }
+
+    #[test]
+    fn retain_unreserved_candidates() {
+        let reserved = read("retain", "prefix z\n");
+        let candidate = |bianma: &str| Candidate {
+            zi: CompactString::from("字"),
+            bianma: CompactString::from(bianma),
+            frequency: 1,
+            stage: Stage::Suffix,
+            factors: Factors { length: 1.0, zigen: 1.0, space: 1.0, family: 1.0, typo: 1.0 },
+            score: 1,
+        };
+        let mut candidates = vec![candidate("za"), candidate("az"), candidate("zo")];
+        reserved.retain_unreserved(&mut candidates);
+        assert_eq!(candidates.iter().map(|cand| cand.bianma.as_str()).collect::<Vec<_>>(), vec!["az"]);
+
+        let mut candidates = vec![candidate("za")];
+        Reserved::default().retain_unreserved(&mut candidates);
+        assert_eq!(candidates.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "第1行缺少类型或模式")]
+    fn missing_pattern() {
+        read("missing", "prefix\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "第2行的类型无效：suffix")]
+    fn unknown_kind() {
+        read("kind", "prefix z\nsuffix a\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "第1行的模式无效")]
+    fn invalid_regex() {
+        read("regex", "regex (a\n");
+    }
+}
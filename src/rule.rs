@@ -0,0 +1,210 @@
+//! 取码规则，用于由字的全码推出词组的编码，写法如 `2:AaBb,3:ABCc,4+:ABCZ`。
+//!
+//! 逗号隔开的每一段以长度开头，`n+` 表示长度不小于 n。冒号后的每个字母取一码：
+//! 字母表示第几个元素（A 为第一个，B 为第二个，以此类推，Z 为最后一个），
+//! 大写取该元素的第一码，小写取第二码。
+
+use std::str::FromStr;
+
+use compact_str::CompactString;
+
+/// 取码时引用的元素位置。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    /// 从前往后数，从 0 开始。
+    Forward(usize),
+    /// 最后一个元素。
+    Last,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LengthRule {
+    length: usize,
+    open_ended: bool,
+    /// 依次取码的位置及码的序号（0 为第一码）。
+    picks: Vec<(Position, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodingRule {
+    rules: Vec<LengthRule>,
+}
+
+/// 默认的构词规则：二字词取 AaBb，三字词取 ABCc，四字及以上取 ABCZ。
+pub const DEFAULT_WORD_RULE: &str = "2:AaBb,3:ABCc,4+:ABCZ";
+
+impl FromStr for CodingRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Vec::new();
+
+        for part in s.split(',').map(|part| part.trim()).filter(|part| !part.is_empty()) {
+            let (length, picks) = part
+                .split_once(':')
+                .ok_or_else(|| format!("取码规则 {part} 缺少冒号"))?;
+
+            let (length, open_ended) = match length.strip_suffix('+') {
+                Some(length) => (length, true),
+                None => (length, false),
+            };
+            let length = length
+                .parse::<usize>()
+                .ok()
+                .filter(|length| *length > 0)
+                .ok_or_else(|| format!("取码规则 {part} 的长度无效"))?;
+
+            let picks = picks
+                .chars()
+                .map(|c| {
+                    let index = if c.is_ascii_uppercase() { 0 } else { 1 };
+                    match c.to_ascii_uppercase() {
+                        'Z' => Ok((Position::Last, index)),
+                        c @ 'A'..='Y' => Ok((Position::Forward(c as usize - 'A' as usize), index)),
+                        _ => Err(format!("取码规则 {part} 含有无效的字母 {c}")),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if picks.is_empty() {
+                return Err(format!("取码规则 {part} 没有取任何码"));
+            }
+            // 长度为 n（或不小于 n）时，只能保证前 n 个元素存在。
+            if let Some(i) = picks
+                .iter()
+                .filter_map(|(position, _)| match position {
+                    Position::Forward(i) => Some(*i),
+                    Position::Last => None,
+                })
+                .find(|i| *i >= length)
+            {
+                return Err(format!("取码规则 {part} 引用了第 {} 个元素，超出了长度 {length}", i + 1));
+            }
+            if rules.iter().any(|rule: &LengthRule| rule.length == length && rule.open_ended == open_ended) {
+                return Err(format!("取码规则 {part} 的长度与前面的规则重复"));
+            }
+
+            rules.push(LengthRule { length, open_ended, picks });
+        }
+
+        if rules.is_empty() {
+            return Err(String::from("取码规则为空"));
+        }
+
+        Ok(Self { rules })
+    }
+}
+
+impl CodingRule {
+    /// 找出适用于给定长度的规则。优先使用长度完全相同的规则，其次是长度不超过它的 `n+` 规则。
+    fn rule_for(&self, length: usize) -> Option<&LengthRule> {
+        self.rules
+            .iter()
+            .find(|rule| !rule.open_ended && rule.length == length)
+            .or_else(|| {
+                self.rules
+                    .iter()
+                    .filter(|rule| rule.open_ended && rule.length <= length)
+                    .max_by_key(|rule| rule.length)
+            })
+    }
+
+    /// 按规则由各元素的编码取码。元素个数没有对应的规则，或者某个元素的码不够取时，返回 None。
+    pub fn apply(&self, elements: &[&str]) -> Option<CompactString> {
+        let rule = self.rule_for(elements.len())?;
+        let mut result = CompactString::new("");
+
+        for &(position, index) in rule.picks.iter() {
+            let element = match position {
+                Position::Forward(i) => elements.get(i)?,
+                Position::Last => elements.last()?,
+            };
+            result.push(element.chars().nth(index)?);
+        }
+
+        Some(result)
+    }
+
+    /// 换写成 libime 与 ibus-table 的构词规则，如 `("e2", "p11+p12+p21+p22")`。
+    /// 规则名以 e 表示长度恰好为 n，以 a 表示长度不小于 n；p 从前往后数，n 从后往前数。
+    pub fn table_rules(&self) -> Vec<(String, String)> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let name = format!("{}{}", if rule.open_ended { 'a' } else { 'e' }, rule.length);
+                let picks = rule.picks
+                    .iter()
+                    .map(|&(position, index)| match position {
+                        Position::Forward(i) => format!("p{}{}", i + 1, index + 1),
+                        Position::Last => format!("n1{}", index + 1),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("+");
+                (name, picks)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(s: &str) -> CodingRule {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn default_rules_parse() {
+        rule(DEFAULT_WORD_RULE);
+        rule(crate::encoder::DEFAULT_ROOT_RULE);
+    }
+
+    #[test]
+    fn apply_word_rule() {
+        let rule = rule(DEFAULT_WORD_RULE);
+        assert_eq!(rule.apply(&["abcd", "efgh"]).unwrap(), "abef");
+        assert_eq!(rule.apply(&["abcd", "efgh", "ijkl"]).unwrap(), "aeij");
+        assert_eq!(rule.apply(&["abcd", "efgh", "ijkl", "mnop", "qrst"]).unwrap(), "aeiq");
+    }
+
+    #[test]
+    fn exact_length_beats_open_ended() {
+        let rule = rule("2+:AB,3:Zz,4+:ABCD");
+        assert_eq!(rule.apply(&["ab", "cd"]).unwrap(), "ac");
+        assert_eq!(rule.apply(&["ab", "cd", "ef"]).unwrap(), "ef");
+        assert_eq!(rule.apply(&["ab", "cd", "ef", "gh", "ij"]).unwrap(), "aceg");
+    }
+
+    #[test]
+    fn missing_length_has_no_code() {
+        let rule = rule("2:AaBb,4:ABCD");
+        assert_eq!(rule.apply(&["ab"]), None);
+        assert_eq!(rule.apply(&["ab", "cd", "ef"]), None);
+        assert_eq!(rule.apply(&["ab", "cd", "ef", "gh", "ij"]), None);
+    }
+
+    #[test]
+    fn short_element_has_no_code() {
+        let rule = rule("2:AaBb");
+        assert_eq!(rule.apply(&["a", "cd"]), None);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for invalid in ["", "2AaBb", "x:AB", "0:A", "2:", "2:A1", "2:AaCc", "3+:ABCD", "2:AB,2:BA", "4+:ABCZ,4+:ABCD"] {
+            assert!(invalid.parse::<CodingRule>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn table_rules() {
+        assert_eq!(
+            rule("2:AaBb,3+:ABZ").table_rules(),
+            vec![
+                (String::from("e2"), String::from("p11+p12+p21+p22")),
+                (String::from("a3"), String::from("p11+p21+n11")),
+            ],
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufWriter, Write};
+
+use argh::FromArgs;
+use compact_str::CompactString;
+
+use crate::evaluate::{assignment_table, shortest_codes};
+use crate::commit::CommitRules;
+use crate::reserve::Reserved;
+use crate::{Inputs, assign_jianma, suffix_jianma_codes};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "suggest-allow")]
+/// 找出被生僻字全码占用的韵码简，逐一试算放行后的得分增益，按可用编码表的格式输出建议。
+pub struct SuggestAllowArgs {
+    #[argh(option, default = "10000")]
+    /// 占用编码的字，其字频不超过此值才视作生僻字。默认为 10000。
+    max_blocker_weight: u64,
+
+    #[argh(option, default = "30")]
+    /// 最多试算的编码数，按放行后可能的最高得分挑选。默认为 30。
+    top: usize,
+}
+
+/// 一个被生僻字全码占用、且不是保留编码的编码。
+struct Blocked {
+    bianma: CompactString,
+    /// 占用该编码的字及其字频。
+    blockers: Vec<(char, u64)>,
+    /// 放行后该编码可能得到的最高得分，用于挑选值得试算的编码。
+    upper_bound: u64,
+}
+
+fn find_blocked(inputs: &Inputs, max_blocker_weight: u64) -> Vec<Blocked> {
+    let mut owners = HashMap::<&str, Vec<(char, u64)>>::new();
+    for (zi, bianma) in inputs.blockers.iter() {
+        let weight = inputs.mabiao.get(zi).map(|ch| ch.weight).unwrap_or(0);
+        owners.entry(bianma.as_str()).or_default().push((*zi, weight));
+    }
+
+    let allowed = inputs.alloweds.iter().collect::<HashSet<_>>();
+    let predefined_codes = inputs.predefineds.iter().map(|pre| &pre.bianma).collect::<HashSet<_>>();
+    let predefined_zi = inputs.predefineds
+        .iter()
+        .filter(|pre| pre.zi.chars().count() == 1)
+        .filter_map(|pre| pre.zi.chars().next())
+        .collect::<HashSet<_>>();
+
+    let mut blocked = HashMap::<CompactString, Blocked>::new();
+    for (zi, character) in inputs.mabiao.iter() {
+        if predefined_zi.contains(zi) {
+            continue
+        }
+
+        for jianma in suffix_jianma_codes(&character.bianma) {
+            if allowed.contains(&jianma) || predefined_codes.contains(&jianma) || inputs.reserved.contains(&jianma) {
+                continue
+            }
+
+            let Some(blockers) = owners.get(jianma.as_str()) else {
+                continue
+            };
+
+            if blockers.iter().any(|(_, weight)| *weight > max_blocker_weight) {
+                continue
+            }
+
+            let entry = blocked.entry(jianma.clone()).or_insert_with(|| Blocked {
+                bianma: jianma,
+                blockers: blockers.clone(),
+                upper_bound: 0,
+            });
+            entry.upper_bound = entry.upper_bound.max(character.weight);
+        }
+    }
+
+    let mut blocked = blocked.into_values().collect::<Vec<_>>();
+    blocked.sort_by(|a, b| a.upper_bound.cmp(&b.upper_bound).reverse().then(a.bianma.cmp(&b.bianma)));
+    blocked
+}
+
+pub fn run_allow<W: Write>(writer: W, args: &SuggestAllowArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+
+    let base_score = assign_jianma(inputs, false).score;
+
+    let mut suggestions = Vec::new();
+    for blocked in find_blocked(inputs, args.max_blocker_weight).into_iter().take(args.top) {
+        let mut alloweds = inputs.alloweds.clone();
+        alloweds.push(blocked.bianma.clone());
+
+        // 按与 generate 相同的方式重新指派，使词组也参与这个编码的争夺。
+        let assignment = assign_jianma(&Inputs { alloweds, ..inputs.clone() }, false);
+        let score = assignment.score;
+
+        if score > base_score {
+            let winner = assignment.selected
+                .iter()
+                .find(|cand| cand.bianma == blocked.bianma)
+                .map(|cand| cand.zi.clone());
+            suggestions.push((blocked, score - base_score, winner));
+        }
+    }
+
+    suggestions.sort_by(|a, b| a.1.cmp(&b.1).reverse());
+
+    writeln!(writer, "# 当前得分：{base_score}").unwrap();
+    for (blocked, gain, winner) in suggestions.iter() {
+        let blockers = blocked.blockers
+            .iter()
+            .map(|(zi, weight)| format!("{zi}（字频 {weight}）"))
+            .collect::<Vec<_>>()
+            .join("、");
+        let winner = winner.as_ref().map(|zi| format!("，给「{zi}」")).unwrap_or_default();
+
+        writeln!(writer, "{} # 阻挡：{blockers}；得分 +{gain}{winner}", blocked.bianma).unwrap();
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "suggest-predefined")]
+/// 建议值得加入预定义编码表的字与词组：没有简码的高频字，以及逐字输入最费键的高频词组。
+pub struct SuggestPredefinedArgs {
+    #[argh(switch)]
+    /// 允许空格简码。
+    space_jianma: bool,
+
+    #[argh(option, default = "30")]
+    /// 单字与词组各自最多列出的条数。默认为 30。
+    top: usize,
+
+    #[argh(option, default = "3")]
+    /// 建议的编码最长的码数。默认为 3。
+    max_length: usize,
+}
+
+/// 从备选编码中挑出击键数最少、尚未被占用、也不是保留编码的一个。
+/// 按上屏规则打完即上屏的编码不能挡住已占用的编码，也不能被已占用的编码挡住。
+fn pick_free_code(
+    options: impl IntoIterator<Item = CompactString>,
+    used: &HashSet<CompactString>,
+    max_length: usize,
+    commit: &CommitRules,
+    reserved: &Reserved,
+) -> Option<CompactString> {
+    options
+        .into_iter()
+        .filter(|bianma| bianma.len() <= max_length && !used.contains(bianma) && !reserved.contains(bianma))
+        .filter(|bianma| commit.blocking_prefix(bianma, used).is_none())
+        .filter(|bianma| !commit.commits_immediately(bianma)
+            || !used.iter().any(|other| other.len() > bianma.len() && other.starts_with(bianma.as_str())))
+        .min_by_key(|bianma| (commit.cost(bianma), bianma.clone()))
+}
+
+/// 由全码推出的备选编码：全码的各个前缀，以及韵码简。
+fn code_options(bianma: &str) -> Vec<CompactString> {
+    (1..bianma.len())
+        .map(|i| CompactString::from(&bianma[..i]))
+        .chain(suffix_jianma_codes(bianma))
+        .collect()
+}
+
+pub fn run_predefined<W: Write>(writer: W, args: &SuggestPredefinedArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+    let commit = &inputs.commit;
+
+    let assignment = assign_jianma(inputs, args.space_jianma);
+    let table = assignment_table(inputs, &assignment, args.space_jianma);
+    let shortest = shortest_codes(&table, commit);
+
+    let mut used = inputs.full_mabiao
+        .values()
+        .map(|ch| ch.bianma.clone())
+        .chain(table.iter().map(|entry| entry.bianma.clone()))
+        .collect::<HashSet<_>>();
+
+    let mut characters = inputs.mabiao
+        .iter()
+        .filter(|(zi, _)| !shortest.contains_key(zi))
+        .collect::<Vec<_>>();
+    characters.sort_by(|a, b| a.1.weight.cmp(&b.1.weight).reverse().then(a.0.cmp(b.0)));
+
+    writeln!(writer, "# 没有简码的高频字").unwrap();
+    let mut count = 0;
+    for (zi, character) in characters {
+        if count >= args.top {
+            break
+        }
+
+        let full_cost = commit.cost(&character.bianma);
+        let Some(bianma) = pick_free_code(code_options(&character.bianma), &used, args.max_length, commit, &inputs.reserved) else {
+            continue
+        };
+
+        let saving = full_cost.saturating_sub(commit.cost(&bianma));
+        if saving == 0 {
+            continue
+        }
+
+        writeln!(
+            writer,
+            "{zi}\t{bianma} # 字频 {}，全码 {}，每次省 {saving} 键，合计省 {}",
+            character.weight,
+            character.bianma,
+            character.weight * saving as u64,
+        ).unwrap();
+
+        used.insert(bianma);
+        count += 1;
+    }
+
+    if inputs.words.is_empty() {
+        return
+    }
+
+    let char_cost = |zi: char| -> Option<usize> {
+        let full = inputs.full_mabiao.get(&zi)?;
+        let full_cost = commit.cost(&full.bianma);
+        Some(shortest
+            .get(&zi)
+            .map(|bianma| commit.cost(bianma).min(full_cost))
+            .unwrap_or(full_cost))
+    };
+
+    let words_with_jianma = table
+        .iter()
+        .map(|entry| entry.zi.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut suggestions = Vec::new();
+    for word in inputs.words.iter() {
+        if words_with_jianma.contains(word.zi.as_str()) {
+            continue
+        }
+
+        let Some(typing_cost) = word.zi.chars().map(char_cost).sum::<Option<usize>>() else {
+            continue
+        };
+        let full_code = &word.bianma;
+
+        let options = (2..full_code.len())
+            .map(|i| CompactString::from(&full_code[..i]))
+            .chain(std::iter::once(full_code.clone()));
+        let Some(bianma) = pick_free_code(options, &used, args.max_length.max(full_code.len()), commit, &inputs.reserved) else {
+            continue
+        };
+
+        let saving = typing_cost.saturating_sub(commit.cost(&bianma));
+        if saving > 0 {
+            suggestions.push((&word.zi, word.weight, typing_cost, bianma, saving as u64 * word.weight));
+        }
+    }
+
+    suggestions.sort_by(|a, b| a.4.cmp(&b.4).reverse().then(a.0.cmp(b.0)));
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "# 逐字输入最费键的高频词组").unwrap();
+    let mut count = 0;
+    for (word, freq, typing_cost, bianma, saving) in suggestions {
+        if count >= args.top {
+            break
+        }
+
+        if !used.insert(bianma.clone()) {
+            continue
+        }
+
+        writeln!(
+            writer,
+            "{word}\t{bianma} # 词频 {freq}，逐字输入 {typing_cost} 键，合计省 {saving}",
+        ).unwrap();
+        count += 1;
+    }
+}
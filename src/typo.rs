@@ -0,0 +1,267 @@
+//! 误触风险：两个编码只差一次相邻键的替换或相邻两码的颠倒时，打错一键便会静默上屏另一个字。
+//! 一对编码的风险取两字字频的几何平均，使两字都常用的组合排在前面。
+
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use argh::FromArgs;
+use compact_str::{CompactString, ToCompactString};
+
+use crate::diff::read_jianma_table;
+use crate::evaluate::{assignment_table, shortest_codes};
+use crate::keyboard::Layout;
+use crate::{Candidate, Inputs, Predefined, assign_jianma};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "typos")]
+/// 分析误触风险：找出各字实际输入的编码中，只差一次相邻键替换或相邻两码颠倒、
+/// 会静默上屏另一个字的编码对，按两字字频的几何平均排列。
+pub struct TyposArgs {
+    #[argh(switch)]
+    /// 计算简码时允许空格简码。
+    space_jianma: bool,
+
+    #[argh(option, default = "30")]
+    /// 最多列出的编码对数，0 表示全部列出。默认为 30。
+    top: usize,
+
+    #[argh(positional)]
+    /// 简码表路径（一行一个汉字与编码，用 \t 隔开）。不指定时分析按当前输入计算出的简码。
+    table: Option<PathBuf>,
+}
+
+/// 打错的方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slip {
+    /// 某一码打成了相邻的键。
+    Substitution,
+    /// 相邻两码打颠倒了。
+    Transposition,
+}
+
+impl Slip {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Substitution => "邻键替换",
+            Self::Transposition => "相邻颠倒",
+        }
+    }
+}
+
+/// 打错一次后可能得到的编码。
+pub fn near_misses(bianma: &str, layout: &Layout) -> Vec<(CompactString, Slip)> {
+    let chars = bianma.chars().collect::<Vec<_>>();
+    let mut result = Vec::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        for key in layout.keys().filter(|key| layout.adjacent(*c, *key)) {
+            let mut slipped = chars.clone();
+            slipped[i] = key;
+            result.push((slipped.into_iter().collect(), Slip::Substitution));
+        }
+    }
+    for i in 1..chars.len() {
+        if chars[i - 1] != chars[i] {
+            let mut slipped = chars.clone();
+            slipped.swap(i - 1, i);
+            result.push((slipped.into_iter().collect(), Slip::Transposition));
+        }
+    }
+
+    result
+}
+
+/// 各编码上首选的字（或词组）及其字频。
+pub type Occupancy = HashMap<CompactString, (CompactString, u64)>;
+
+fn occupy(occupancy: &mut Occupancy, bianma: &CompactString, zi: &CompactString, weight: u64) {
+    let entry = occupancy.entry(bianma.clone()).or_insert_with(|| (zi.clone(), weight));
+    if weight > entry.1 {
+        *entry = (zi.clone(), weight);
+    }
+}
+
+fn predefined_weight(inputs: &Inputs, pre: &Predefined) -> u64 {
+    let mut chars = pre.zi.chars();
+    match (chars.next(), chars.next()) {
+        (Some(zi), None) => inputs.mabiao.get(&zi).map(|ch| ch.weight).unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// 不随简码指派而变的编码：各字的全码，以及预定义与额外定义的编码。
+pub fn fixed_occupancy(inputs: &Inputs) -> Occupancy {
+    let mut occupancy = Occupancy::new();
+
+    for (zi, character) in inputs.mabiao.iter() {
+        let zi = zi.to_compact_string();
+        occupy(&mut occupancy, &character.bianma, &zi, character.weight);
+    }
+    for pre in inputs.predefineds.iter().chain(inputs.additionals.iter()) {
+        occupy(&mut occupancy, &pre.bianma, &pre.zi, predefined_weight(inputs, pre));
+    }
+
+    occupancy
+}
+
+/// 按上一轮的结果更新各候选的误触系数：系数 = 1 / (1 + 惩罚 × Σ 风险 / 本字字频)，
+/// 其中 Σ 对打错一次后会上屏其他字的编码求和，风险为两字字频的几何平均。
+pub fn update_typo_factor(
+    candidates: &mut [Candidate],
+    fixed: &Occupancy,
+    assigned: &[&Candidate],
+    layout: &Layout,
+    penalty: f64,
+) {
+    let mut occupancy = fixed.clone();
+    for cand in assigned.iter() {
+        occupy(&mut occupancy, &cand.bianma, &cand.zi, cand.frequency);
+    }
+
+    for cand in candidates.iter_mut() {
+        let weight = cand.frequency.max(1) as f64;
+        let risk = near_misses(&cand.bianma, layout)
+            .iter()
+            .filter_map(|(slipped, _)| occupancy.get(slipped))
+            .filter(|(zi, _)| *zi != cand.zi)
+            .map(|(_, other)| (weight * *other as f64).sqrt())
+            .sum::<f64>();
+
+        cand.factors.typo = 1.0 / (1.0 + penalty * risk / weight);
+    }
+}
+
+/// 一对误触编码。
+struct RiskyPair {
+    intended: char,
+    bianma: CompactString,
+    other: CompactString,
+    slipped: CompactString,
+    slip: Slip,
+    risk: f64,
+}
+
+pub fn run<W: Write>(writer: W, args: &TyposArgs, inputs: &Inputs) {
+    let mut writer = BufWriter::new(writer);
+
+    let table = match &args.table {
+        Some(path) => read_jianma_table(path),
+        None => {
+            let assignment = assign_jianma(inputs, args.space_jianma);
+            assignment_table(inputs, &assignment, args.space_jianma)
+        }
+    };
+    let shortest = shortest_codes(&table, &inputs.commit);
+
+    let mut occupancy = fixed_occupancy(inputs);
+    for entry in table.iter() {
+        occupy(&mut occupancy, &entry.bianma, &entry.zi, predefined_weight(inputs, entry));
+    }
+
+    let total_weight = inputs.mabiao.values().map(|ch| ch.weight).sum::<u64>().max(1) as f64;
+    let mut exposed_weight = 0;
+    let mut pairs = Vec::new();
+
+    for (zi, character) in inputs.mabiao.iter() {
+        // 每个字按最短的编码输入。
+        let bianma = shortest
+            .get(zi)
+            .filter(|bianma| bianma.len() <= character.bianma.len())
+            .copied()
+            .unwrap_or(&character.bianma);
+        let zi_str = zi.to_compact_string();
+
+        let mut exposed = false;
+        for (slipped, slip) in near_misses(bianma, &inputs.layout) {
+            let Some((other, weight)) = occupancy.get(&slipped) else {
+                continue
+            };
+            if *other == zi_str || *weight == 0 {
+                continue
+            }
+
+            exposed = true;
+            pairs.push(RiskyPair {
+                intended: *zi,
+                bianma: bianma.clone(),
+                other: other.clone(),
+                slipped,
+                slip,
+                risk: (character.weight as f64 * *weight as f64).sqrt(),
+            });
+        }
+        if exposed {
+            exposed_weight += character.weight;
+        }
+    }
+    pairs.sort_by(|a, b| b.risk.total_cmp(&a.risk).then(a.intended.cmp(&b.intended)).then(a.slipped.cmp(&b.slipped)));
+
+    writeln!(writer, "误触编码对：{} 对", pairs.len()).unwrap();
+    writeln!(writer, "打错一次可能静默上屏其他字的字（按字频加权）：{:.2}%", exposed_weight as f64 / total_weight * 100.0).unwrap();
+    writeln!(writer, "总风险：{:.0}", pairs.iter().map(|pair| pair.risk).sum::<f64>()).unwrap();
+
+    writeln!(writer).unwrap();
+    writeln!(writer, "风险最高的编码对（本字、编码、误触后上屏的字与编码、方式、风险）：").unwrap();
+    let limit = if args.top == 0 { pairs.len() } else { args.top };
+    for pair in pairs.iter().take(limit) {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{:.0}",
+            pair.intended,
+            pair.bianma,
+            pair.other,
+            pair.slipped,
+            pair.slip.name(),
+            pair.risk,
+        ).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn misses(bianma: &str) -> Vec<(String, Slip)> {
+        let mut result = near_misses(bianma, &Layout::qwerty())
+            .into_iter()
+            .map(|(slipped, slip)| (slipped.to_string(), slip))
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| a.0.cmp(&b.0));
+        result
+    }
+
+    #[test]
+    fn single_key_substitutions() {
+        assert_eq!(
+            misses("a"),
+            vec![
+                (String::from("q"), Slip::Substitution),
+                (String::from("s"), Slip::Substitution),
+                (String::from("w"), Slip::Substitution),
+                (String::from("z"), Slip::Substitution),
+            ],
+        );
+    }
+
+    #[test]
+    fn substitutions_and_transpositions() {
+        let result = misses("ab");
+        assert!(result.contains(&(String::from("ba"), Slip::Transposition)));
+        assert!(result.contains(&(String::from("sb"), Slip::Substitution)));
+        assert!(result.contains(&(String::from("ag"), Slip::Substitution)));
+        assert!(!result.iter().any(|(slipped, _)| slipped == "ab"));
+        // a 有 4 个邻键，b 有 v、n、g、h 4 个邻键，外加一次颠倒。
+        assert_eq!(result.len(), 9);
+    }
+
+    #[test]
+    fn no_transposition_of_repeated_keys() {
+        assert!(!misses("aa").iter().any(|(_, slip)| *slip == Slip::Transposition));
+    }
+
+    #[test]
+    fn punctuation_neighbours_are_included() {
+        assert!(misses("l").contains(&(String::from(";"), Slip::Substitution)));
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufWriter, Write};
+
+use argh::FromArgs;
+use compact_str::CompactString;
+
+use crate::keyboard::Layout;
+use crate::{Inputs, Predefined};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "validate")]
+/// 检查可用编码表、预定义编码表与额外定义编码表中的错误。发现问题时以非零状态退出。
+pub struct ValidateArgs {}
+
+/// 按问题类别收集检查结果，输出时保持类别的先后次序。
+#[derive(Default)]
+struct Report {
+    issues: Vec<(&'static str, Vec<String>)>,
+}
+
+impl Report {
+    fn add(&mut self, kind: &'static str, message: String) {
+        match self.issues.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, messages)) => messages.push(message),
+            None => self.issues.push((kind, vec![message])),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+fn check_alphabet(report: &mut Report, file: &str, entries: &[Predefined], layout: &Layout) {
+    for entry in entries.iter() {
+        if !layout.is_valid_bianma(&entry.bianma) {
+            report.add("编码含有字母表以外的字符", format!("{file}：{}\t{}", entry.zi, entry.bianma));
+        }
+    }
+}
+
+fn check_missing_zi(report: &mut Report, file: &str, entries: &[Predefined], inputs: &Inputs) {
+    for entry in entries.iter() {
+        let missing = entry.zi
+            .chars()
+            .filter(|zi| !inputs.full_mabiao.contains_key(zi))
+            .collect::<String>();
+
+        if !missing.is_empty() {
+            report.add(
+                "字不在码表中",
+                format!("{file}：{}\t{}（{missing}）", entry.zi, entry.bianma),
+            );
+        }
+    }
+}
+
+fn check(inputs: &Inputs) -> Report {
+    let mut report = Report::default();
+
+    for error in inputs.malformed.iter() {
+        report.add("格式错误的行", error.clone());
+    }
+
+    let mut allow_count = HashMap::<&CompactString, usize>::new();
+    for bianma in inputs.alloweds.iter() {
+        *allow_count.entry(bianma).or_default() += 1;
+    }
+    let mut duplicated = allow_count.iter().filter(|(_, n)| **n > 1).collect::<Vec<_>>();
+    duplicated.sort();
+    for (bianma, n) in duplicated {
+        report.add("重复的编码", format!("可用编码表：{bianma} 出现了 {n} 次"));
+    }
+
+    let mut predefined_by_code = BTreeMap::<&CompactString, Vec<&CompactString>>::new();
+    let mut predefined_by_zi = BTreeMap::<&CompactString, Vec<&CompactString>>::new();
+    for pre in inputs.predefineds.iter() {
+        predefined_by_code.entry(&pre.bianma).or_default().push(&pre.zi);
+        predefined_by_zi.entry(&pre.zi).or_default().push(&pre.bianma);
+    }
+
+    for (bianma, zis) in predefined_by_code.iter().filter(|(_, zis)| zis.len() > 1) {
+        let zis = zis.iter().map(|zi| zi.as_str()).collect::<Vec<_>>().join("、");
+        report.add("重复的编码", format!("预定义编码表：{bianma} 同时指定给了 {zis}"));
+    }
+
+    let mut seen = HashSet::new();
+    for add in inputs.additionals.iter() {
+        if !seen.insert((&add.zi, &add.bianma)) {
+            report.add("重复的编码", format!("额外定义编码表：{}\t{} 出现了多次", add.zi, add.bianma));
+        }
+    }
+
+    for (zi, bianmas) in predefined_by_zi.iter().filter(|(_, bianmas)| bianmas.len() > 1) {
+        let bianmas = bianmas.iter().map(|b| b.as_str()).collect::<Vec<_>>().join("、");
+        report.add("同一字被预定义多次", format!("{zi}：{bianmas}"));
+    }
+
+    for add in inputs.additionals.iter() {
+        if let Some(zis) = predefined_by_code.get(&add.bianma) {
+            let zis = zis.iter().map(|zi| zi.as_str()).collect::<Vec<_>>().join("、");
+            report.add(
+                "预定义编码与额外定义编码冲突",
+                format!("{}：预定义给 {zis}，额外定义给 {}", add.bianma, add.zi),
+            );
+        }
+    }
+
+    let full_codes = inputs.blockers
+        .values()
+        .collect::<HashSet<_>>();
+    for bianma in inputs.alloweds.iter() {
+        if !full_codes.contains(bianma) {
+            report.add("可用编码未被任何全码占用，无需列出", bianma.to_string());
+        }
+    }
+
+    for bianma in inputs.alloweds.iter() {
+        if !inputs.layout.is_valid_bianma(bianma) {
+            report.add("编码含有字母表以外的字符", format!("可用编码表：{bianma}"));
+        }
+    }
+    check_alphabet(&mut report, "预定义编码表", &inputs.predefineds, &inputs.layout);
+    check_alphabet(&mut report, "额外定义编码表", &inputs.additionals, &inputs.layout);
+
+    for (file, entries) in [("预定义编码表", &inputs.predefineds), ("额外定义编码表", &inputs.additionals)] {
+        for entry in entries.iter() {
+            if let Some(pattern) = inputs.reserved.matching(&entry.bianma) {
+                report.add("编码落在保留编码中", format!("{file}：{}\t{}（{pattern}）", entry.zi, entry.bianma));
+            }
+        }
+    }
+
+    check_missing_zi(&mut report, "预定义编码表", &inputs.predefineds, inputs);
+    check_missing_zi(&mut report, "额外定义编码表", &inputs.additionals, inputs);
+
+    for pre in inputs.predefineds.iter() {
+        let mut chars = pre.zi.chars();
+        let (Some(zi), None) = (chars.next(), chars.next()) else {
+            continue
+        };
+
+        if let Some(character) = inputs.full_mabiao.get(&zi)
+            && pre.bianma.len() > character.bianma.len()
+        {
+            report.add(
+                "预定义编码比全码还长",
+                format!("{}\t{}（全码 {}）", pre.zi, pre.bianma, character.bianma),
+            );
+        }
+    }
+
+    report
+}
+
+pub fn run<W: Write>(writer: W, _args: &ValidateArgs, inputs: &Inputs) {
+    let report = check(inputs);
+
+    {
+        let mut writer = BufWriter::new(writer);
+
+        writeln!(writer, "码表：{} 字，其中 {} 字参与简码计算", inputs.full_mabiao.len(), inputs.mabiao.len()).unwrap();
+        writeln!(writer, "可用编码表：{} 条", inputs.alloweds.len()).unwrap();
+        writeln!(writer, "预定义编码表：{} 条", inputs.predefineds.len()).unwrap();
+        writeln!(writer, "额外定义编码表：{} 条", inputs.additionals.len()).unwrap();
+
+        if report.is_empty() {
+            writeln!(writer, "未发现问题。").unwrap();
+            return;
+        }
+
+        for (kind, messages) in report.issues.iter() {
+            writeln!(writer).unwrap();
+            writeln!(writer, "{kind}（{} 处）：", messages.len()).unwrap();
+            for message in messages.iter() {
+                writeln!(writer, "  {message}").unwrap();
+            }
+        }
+    }
+
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{parse_predefined_line, read_allow_file, read_predefined_file};
+
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("makejian-{}-validate-{name}.txt", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn entry(zi: &str, bianma: &str) -> Predefined {
+        Predefined { bianma: CompactString::from(bianma), zi: CompactString::from(zi) }
+    }
+
+    #[test]
+    fn report_keeps_kinds_in_order() {
+        let mut report = Report::default();
+        assert!(report.is_empty());
+        report.add("乙", String::from("1"));
+        report.add("甲", String::from("2"));
+        report.add("乙", String::from("3"));
+        assert_eq!(report.issues, vec![
+            ("乙", vec![String::from("1"), String::from("3")]),
+            ("甲", vec![String::from("2")]),
+        ]);
+    }
+
+    #[test]
+    fn alphabet_of_layout() {
+        let mut report = Report::default();
+        let entries = [entry("的", "e"), entry("了", "l;"), entry("是", "u1")];
+        check_alphabet(&mut report, "预定义编码表", &entries, &Layout::qwerty());
+        assert_eq!(report.issues, vec![
+            ("编码含有字母表以外的字符", vec![String::from("预定义编码表：了\tl;"), String::from("预定义编码表：是\tu1")]),
+        ]);
+    }
+
+    #[test]
+    fn predefined_lines() {
+        assert_eq!(parse_predefined_line("预定义编码表", 0, "的\tE "), Ok(entry("的", "e")));
+        assert_eq!(parse_predefined_line("预定义编码表", 0, "一下\tyx"), Ok(entry("一下", "yx")));
+        // 全角空格也是要预定义的字。
+        assert_eq!(parse_predefined_line("预定义编码表", 0, "\u{3000}\tkg"), Ok(entry("\u{3000}", "kg")));
+
+        for line in ["的 e", "\te", "的\t ", "的"] {
+            let error = parse_predefined_line("预定义编码表", 4, line).unwrap_err();
+            assert!(error.starts_with("预定义编码表第5行存在错误"), "{error}");
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_collected() {
+        let path = temp_file("allow", "# 可用编码\nAB\nab cd\n\nef\n");
+        let (alloweds, errors) = read_allow_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(alloweds, vec!["ab", "ef"]);
+        assert_eq!(errors, vec![String::from("可用编码表第3行应只有一个编码：ab cd")]);
+
+        let path = temp_file("predefined", "的\te\n了 le\n是\tu\n");
+        let (predefineds, errors) = read_predefined_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(predefineds, vec![entry("的", "e"), entry("是", "u")]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("预定义编码表第2行存在错误"));
+    }
+}
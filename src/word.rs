@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use compact_str::CompactString;
+
+use crate::rule::CodingRule;
+use crate::{Candidate, Character, Factors, Predefined, Stage, read_file};
+
+/// 参与简码计算的词组。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub zi: CompactString,
+    /// 按构词规则推出的全码。
+    pub bianma: CompactString,
+    /// 乘上混合权重后的词频。
+    pub weight: u64,
+}
+
+/// 读取词频表，一行一个词组与词频（用 \t 隔开），# 开头的行会被忽略。
+/// 只保留两字及以上的词组，按词频降序排列。
+pub fn read_word_frequency(path: &Path) -> Vec<(CompactString, u64)> {
+    let mut result = Vec::new();
+
+    read_file(path, "词频表", |line_no, line| {
+        let Some((word, freq)) = line.split_once('\t') else {
+            panic!("词频表第{}行存在错误", line_no + 1);
+        };
+
+        let freq = freq
+            .trim_ascii()
+            .parse::<f64>()
+            .unwrap_or_else(|_| panic!("词频表第{}行的词频无效", line_no + 1));
+
+        if word.chars().count() >= 2 {
+            result.push((CompactString::new(word), freq.round() as u64));
+        }
+    });
+
+    result.sort_by(|a, b| a.1.cmp(&b.1).reverse().then(a.0.cmp(&b.0)));
+    result
+}
+
+/// 按构词规则，由词中各字的全码推出词组的全码。词中有字不在码表中时返回 None。
+pub fn word_full_code(
+    word: &str,
+    mabiao: &HashMap<char, Character>,
+    rule: &CodingRule,
+) -> Option<CompactString> {
+    let codes = word
+        .chars()
+        .map(|zi| mabiao.get(&zi).map(|ch| ch.bianma.as_str()))
+        .collect::<Option<Vec<_>>>()?;
+
+    rule.apply(&codes)
+}
+
+/// 读取词频表，按构词规则推出各词组的全码。推不出全码的词组会被略去。
+pub fn load_words(
+    path: &Path,
+    mabiao: &HashMap<char, Character>,
+    rule: &CodingRule,
+    weight: f64,
+) -> Vec<Word> {
+    read_word_frequency(path)
+        .into_iter()
+        .filter_map(|(zi, freq)| {
+            let bianma = word_full_code(&zi, mabiao, rule)?;
+            let weight = (freq as f64 * weight).round() as u64;
+            Some(Word { zi, bianma, weight })
+        })
+        .collect()
+}
+
+/// 预定义编码表中列出的词组不参与计算。
+fn predefined_words(predefineds: &[Predefined]) -> HashSet<&str> {
+    predefineds
+        .iter()
+        .filter(|pre| pre.zi.chars().count() >= 2)
+        .map(|pre| pre.zi.as_str())
+        .collect()
+}
+
+/// 词组的韵码简候选：取词组全码以B区键结尾的前缀，两码至四码。
+/// 与单字的韵码简一同求解，占用全码的字（blockers）的全码与预定义编码视作不可用编码。
+pub fn make_word_jianma_candidate(
+    words: &[Word],
+    blockers: &HashMap<char, CompactString>,
+    allowed: &[CompactString],
+    predefineds: &[Predefined],
+    b_area: &[char],
+) -> Vec<Candidate> {
+    let mut result = Vec::new();
+    let mut unavailable_bianma = blockers
+        .values()
+        .map(|bianma| bianma.as_str())
+        .collect::<HashSet<_>>();
+    unavailable_bianma.extend(predefineds.iter().map(|pre| pre.bianma.as_str()));
+    for bianma in allowed.iter() {
+        unavailable_bianma.remove(bianma.as_str());
+    }
+
+    let predefineds = predefined_words(predefineds);
+
+    for word in words.iter() {
+        if predefineds.contains(word.zi.as_str()) {
+            continue
+        }
+
+        // 构词规则可能推出五码以上的全码，码长差按五码封顶，简码也至多取四码。
+        for i in 2..word.bianma.len().min(5) {
+            let jianma = &word.bianma[..i];
+
+            if !jianma.ends_with(b_area) || unavailable_bianma.contains(jianma) {
+                continue
+            }
+
+            let jianma_diff = (word.bianma.len().min(5) - jianma.len()) as f64;
+            let factors = Factors {
+                length: f64::powf(1.8, jianma_diff - 1.0),
+                zigen: 1.0,
+                space: 1.0,
+                family: 1.0,
+                typo: 1.0,
+            };
+            let jianma_weight = word.weight as f64
+                * factors.length
+                * factors.zigen
+                * factors.space;
+
+            if jianma_weight > 8000.0 {
+                result.push(Candidate {
+                    zi: word.zi.clone(),
+                    bianma: CompactString::from(jianma),
+                    frequency: word.weight,
+                    stage: Stage::Suffix,
+                    factors,
+                    score: jianma_weight as u64,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// 词组的空格简候选：取词组全码的前缀，至少两码。已有三码以内韵码简的词组不再参与。
+pub fn make_word_space_jianma_candidate(
+    words: &[Word],
+    suffix_jianma: &[Candidate],
+    predefineds: &[Predefined],
+    b_area: &[char],
+) -> Vec<Candidate> {
+    let mut result = Vec::new();
+    let unavailable_bianma = predefineds
+        .iter()
+        .filter(|pre| !pre.bianma.ends_with(b_area))
+        .map(|pre| pre.bianma.as_str())
+        .collect::<HashSet<_>>();
+
+    let suffix_jianma_len = suffix_jianma
+        .iter()
+        .map(|cand| (cand.zi.as_str(), cand.bianma.len()))
+        .collect::<HashMap<_, _>>();
+
+    let predefineds = predefined_words(predefineds);
+
+    for word in words.iter() {
+        if predefineds.contains(word.zi.as_str()) {
+            continue
+        }
+
+        let max_jianma_len = match suffix_jianma_len.get(word.zi.as_str()) {
+            Some(&len) if len <= 3 => continue,
+            Some(&len) => len - 1,
+            None => 4,
+        };
+
+        for i in 2..(word.bianma.len() - 1).min(max_jianma_len) {
+            let jianma = &word.bianma[..i];
+
+            if unavailable_bianma.contains(jianma) {
+                continue
+            }
+
+            let jianma_diff = (word.bianma.len().min(5) - jianma.len() - 1) as f64;
+            let factors = Factors {
+                length: f64::powf(1.8, jianma_diff - 1.0),
+                zigen: 1.0,
+                space: f64::powf(10.0, -(jianma.len().saturating_sub(2) as f64)),
+                family: 1.0,
+                typo: 1.0,
+            };
+            let jianma_weight = word.weight as f64
+                * factors.length
+                * factors.zigen
+                * factors.space;
+
+            if jianma_weight > 30000.0 {
+                result.push(Candidate {
+                    zi: word.zi.clone(),
+                    bianma: CompactString::from(jianma),
+                    frequency: word.weight,
+                    stage: Stage::Space,
+                    factors,
+                    score: jianma_weight as u64,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(rule: &str, codes: &[&str], weight: u64) -> Word {
+        let bianma = rule.parse::<CodingRule>().unwrap().apply(codes).unwrap();
+        Word { zi: CompactString::from("词组"), bianma, weight }
+    }
+
+    fn candidates(words: &[Word]) -> Vec<Candidate> {
+        make_word_jianma_candidate(words, &HashMap::new(), &[], &[], &['a', 'e', 'i', 'o', 'u'])
+    }
+
+    #[test]
+    fn default_rule_jianma() {
+        let words = [word(crate::rule::DEFAULT_WORD_RULE, &["bace", "dofu"], 100_000)];
+        assert_eq!(words[0].bianma, "bado");
+        assert_eq!(candidates(&words).iter().map(|cand| cand.bianma.as_str()).collect::<Vec<_>>(), vec!["ba"]);
+    }
+
+    #[test]
+    fn long_word_rule_does_not_overflow() {
+        // 八码的全码：简码只取前四码以内以B区键结尾的前缀。
+        let words = [word("2:AaBbAaBb", &["bace", "dofu"], 100_000)];
+        assert_eq!(words[0].bianma, "badobado");
+        let candidates = candidates(&words);
+        assert_eq!(
+            candidates.iter().map(|cand| cand.bianma.as_str()).collect::<Vec<_>>(),
+            vec!["ba", "bado"],
+        );
+        assert!(candidates.iter().all(|cand| cand.factors.length.is_finite() && cand.score > 0));
+    }
+}